@@ -0,0 +1,241 @@
+use arrayvec::ArrayVec;
+
+use game::{pieces::Piece, rng::Rng, tetris_board::TetrisBoard};
+
+use crate::{eval::TOP_OUT, TetrisAi};
+
+/// Settings for `TetrisAi::find_best_move_beam`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Beam {
+    /// Boards kept after every piece. Once the pieces aren't known anymore, this many are kept for
+    /// every piece that can come.
+    pub width: usize,
+    /// Pieces placed, including the current one.
+    pub depth: usize,
+}
+
+impl Default for Beam {
+    fn default() -> Self {
+        Self { width: 8, depth: 3 }
+    }
+}
+
+/// A board in the beam.
+struct Node<B> {
+    board: B,
+    score: u32,
+    /// Index of the node this one was placed on, in the level before.
+    parent: usize,
+    /// Index of the piece placed, in the pieces of its level.
+    piece: usize,
+    /// Best score right after placing each piece of the next level, for the pieces whose boards
+    /// were all cut from the beam.
+    fallback: ArrayVec<f32, 7>,
+}
+
+impl<R: Rng, B: TetrisBoard> TetrisAi<R, B> {
+    /// Places `settings.depth` pieces in a row, keeping only the `settings.width` best boards
+    /// after every piece, and picks the move of the current piece that can be expected to lead to
+    /// the best board. The current and next pieces are known. After those, every piece that can
+    /// come gets a beam of its own, and the boards are averaged with the probabilities of `rng`
+    /// like `find_best_move_expectimax` does, so one lucky piece can't carry a move.
+    pub fn find_best_move_beam(&self, settings: Beam) -> Option<(B::Pos, u32)> {
+        let probabilities = self.game.rng.probabilities();
+
+        let candidates = self.candidates(&self.game.board, self.game.pos, settings.width);
+        let first: Vec<_> = candidates.iter().map(|candidate| candidate.pos).collect();
+
+        let mut levels = vec![candidates
+            .into_iter()
+            .map(|candidate| Node {
+                board: candidate.board,
+                score: candidate.score,
+                parent: 0,
+                piece: 0,
+                fallback: ArrayVec::new(),
+            })
+            .collect::<Vec<_>>()];
+        // Pieces of every level after the first, with their probabilities.
+        let mut level_pieces = Vec::new();
+
+        for ply in 1..settings.depth {
+            let pieces: ArrayVec<(Piece, f32), 7> = match ply {
+                1 => [(self.game.next, 1.0)].into_iter().collect(),
+                _ => Piece::PIECES
+                    .into_iter()
+                    .zip(probabilities)
+                    .filter(|&(_, p)| p > 0.0)
+                    .collect(),
+            };
+
+            // The boards of every piece, kept apart so each piece gets its own beam.
+            let mut beams: Vec<Vec<Node<B>>> = pieces.iter().map(|_| Vec::new()).collect();
+
+            for (parent, node) in levels.last_mut().unwrap().iter_mut().enumerate() {
+                for (i, &(piece, _)) in pieces.iter().enumerate() {
+                    let start = B::spawn_pos(piece);
+
+                    let candidates = match node.board.collides(start) {
+                        true => ArrayVec::new(),
+                        false => self.candidates(&node.board, start, settings.width),
+                    };
+
+                    let best = candidates.first().map_or(TOP_OUT, |c| c.score as f32);
+                    node.fallback.push(best);
+
+                    beams[i].extend(candidates.into_iter().map(|candidate| Node {
+                        board: candidate.board,
+                        score: candidate.score,
+                        parent,
+                        piece: i,
+                        fallback: ArrayVec::new(),
+                    }));
+                }
+            }
+
+            let mut level = Vec::new();
+            for mut beam in beams {
+                beam.sort_by_key(|node| node.score);
+                beam.truncate(settings.width);
+                level.extend(beam);
+            }
+
+            levels.push(level);
+            level_pieces.push(pieces);
+        }
+
+        // Works out the value of every node from the level below it, going back up: the best
+        // board of every piece, averaged over the pieces.
+        let mut values: Vec<f32> = levels
+            .last()?
+            .iter()
+            .map(|node| node.score as f32)
+            .collect();
+
+        for (level, pieces) in levels.windows(2).zip(&level_pieces).rev() {
+            let (parents, children) = (&level[0], &level[1]);
+            let mut best = vec![[None::<f32>; 7]; parents.len()];
+
+            for (child, value) in children.iter().zip(values) {
+                let best = &mut best[child.parent][child.piece];
+                *best = Some(best.map_or(value, |best| best.min(value)));
+            }
+
+            let total: f32 = pieces.iter().map(|&(_, p)| p).sum();
+
+            values = parents
+                .iter()
+                .zip(best)
+                .map(|(parent, best)| {
+                    let score: f32 = (pieces.iter().zip(&parent.fallback).zip(best))
+                        .map(|((&(_, p), &fallback), best)| p * best.unwrap_or(fallback))
+                        .sum();
+                    score / total
+                })
+                .collect();
+        }
+
+        values
+            .into_iter()
+            .zip(first)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(score, pos)| (pos, score.min(TOP_OUT) as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{board::Board, rng::SevenBag, row_board::RowBoard};
+
+    use super::*;
+    use crate::expectimax::Expectimax;
+
+    #[test]
+    fn wide_beam_over_two_pieces_matches_two_ply() {
+        let beam = Beam {
+            width: 10_000,
+            depth: 2,
+        };
+
+        crate::assert_matches_two_ply::<Board>(|ai| ai.find_best_move_beam(beam));
+        crate::assert_matches_two_ply::<RowBoard>(|ai| ai.find_best_move_beam(beam));
+    }
+
+    #[test]
+    fn narrow_beam_keeps_the_greedy_move() {
+        let ai = TetrisAi::<SevenBag>::from_seed(5, 19);
+        let (greedy, _) = ai.find_best_move().unwrap();
+
+        let (pos, score) = ai.find_best_move_beam(Beam { width: 1, depth: 2 }).unwrap();
+
+        let mut board = ai.game.board.clone();
+        TetrisBoard::lock(&mut board, greedy);
+        board.clear_lines();
+
+        assert_eq!(pos, greedy);
+        assert_eq!(score, ai.best_score(&board, ai.game.next));
+        assert_ne!(
+            Some((pos, score)),
+            ai.find_best_move_beam(Beam {
+                width: 10_000,
+                depth: 2
+            })
+        );
+    }
+
+    #[test]
+    fn averages_over_the_pieces_after_next() {
+        let ai = TetrisAi::<SevenBag>::from_seed(10, 19);
+        let exact = Expectimax {
+            depth: 1,
+            width: 100,
+            min_probability: 0.0,
+        };
+
+        let (pos, score) = ai
+            .find_best_move_beam(Beam {
+                width: 10_000,
+                depth: 3,
+            })
+            .unwrap();
+
+        assert_eq!((pos, score), ai.find_best_move_expectimax(exact).unwrap());
+
+        // Betting on the best piece after next to come picks another move.
+        let next = Board::spawn_pos(ai.game.next);
+        let pieces = Piece::PIECES
+            .into_iter()
+            .zip(ai.game.rng.probabilities())
+            .filter(|&(_, p)| p > 0.0);
+
+        let luckiest = ai
+            .candidates(&ai.game.board, ai.game.pos, 100)
+            .into_iter()
+            .min_by_key(|candidate| {
+                ai.candidates(&candidate.board, next, 100)
+                    .iter()
+                    .flat_map(|c| {
+                        pieces
+                            .clone()
+                            .map(|(piece, _)| ai.best_score(&c.board, piece))
+                    })
+                    .min()
+                    .unwrap_or(u32::MAX)
+            })
+            .unwrap();
+
+        assert_ne!(pos, luckiest.pos);
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let beam = Beam { width: 4, depth: 3 };
+
+        crate::assert_plays_a_game::<Board>(30, |ai| {
+            ai.find_best_move_beam(beam).map(|(pos, _)| pos)
+        });
+        crate::assert_plays_a_game::<RowBoard>(30, |ai| {
+            ai.find_best_move_beam(beam).map(|(pos, _)| pos)
+        });
+    }
+}
@@ -0,0 +1,225 @@
+//! Plays the same random moves on a `Board` and a `RowBoard` and checks that both engines agree
+//! on every step, so mistakes in either rotation table show up without writing a test per case.
+
+use std::collections::HashSet;
+
+use nanorand::{Rng as _, WyRand};
+
+use game::{board::*, pieces::Piece, rng::SequenceRng, row_board::*, tetris_board::*, RowGame};
+
+use crate::{profile::InputProfile, row_ai::RowTetrisAi, TetrisAi};
+
+const GAMES: u64 = 200;
+const MOVES: usize = 400;
+
+#[derive(Debug, Clone, Copy)]
+enum Move {
+    Left,
+    Right,
+    Down,
+    RotateCW,
+    RotateCCW,
+    Lock,
+}
+
+impl Move {
+    const MOVES: [Move; 5] = [
+        Move::Left,
+        Move::Right,
+        Move::Down,
+        Move::RotateCW,
+        Move::RotateCCW,
+    ];
+
+    fn apply<B: TetrisBoard>(self, board: &B, pos: B::Pos) -> Option<B::Pos> {
+        match self {
+            Move::Left => board.try_left(pos),
+            Move::Right => board.try_right(pos),
+            Move::Down => board.try_down(pos),
+            Move::RotateCW => board.try_rot_cw(pos),
+            Move::RotateCCW => board.try_rot_ccw(pos),
+            Move::Lock => None,
+        }
+    }
+}
+
+fn assert_same_cells(board: &Board, row_board: &RowBoard) {
+    assert_eq!(
+        board.features(),
+        &BoardFeatures::new(board),
+        "stale features\n{board}"
+    );
+    assert_eq!(row_board.features(), board.features());
+
+    for y in 0..BOARD_SIZE / BOARD_WIDTH {
+        for x in 0..FIELD_WIDTH {
+            assert_eq!(
+                board.filled(x, y),
+                row_board.filled(x, y),
+                "cell ({x}, {y}) differs\n{board}\n{row_board}"
+            );
+        }
+    }
+}
+
+/// Plays a random game on both boards at once, calling `check` with both boards before every
+/// piece. Moves are mostly downwards, so pieces get locked at all heights.
+fn play(seed: u64, mut check: impl FnMut(&Board, &RowBoard, Piece)) {
+    let mut rng = WyRand::new_seed(seed);
+    let mut board = Board::new();
+    let mut row_board = RowBoard::new();
+
+    let mut piece = Piece::PIECES[rng.generate_range(0..7usize)];
+    let mut pos = Board::spawn_pos(piece);
+    let mut row_pos = RowBoard::spawn_pos(piece);
+    check(&board, &row_board, piece);
+
+    for _ in 0..MOVES {
+        let mv = match rng.generate_range(0..10usize) {
+            0..=5 => Move::MOVES[rng.generate_range(0..Move::MOVES.len())],
+            6..=8 => Move::Down,
+            _ => Move::Lock,
+        };
+
+        let moved = mv.apply(&board, pos);
+        let row_moved = mv.apply(&row_board, row_pos);
+
+        assert_eq!(
+            moved.map(Board::placement),
+            row_moved.map(RowBoard::placement),
+            "{mv:?} from {:?} differs\n{board}",
+            Board::placement(pos),
+        );
+
+        if let (Some(moved), Some(row_moved)) = (moved, row_moved) {
+            pos = moved;
+            row_pos = row_moved;
+            continue;
+        }
+
+        if !matches!(mv, Move::Lock) {
+            continue;
+        }
+
+        while let (Some(moved), Some(row_moved)) = (
+            TetrisBoard::try_down(&board, pos),
+            TetrisBoard::try_down(&row_board, row_pos),
+        ) {
+            pos = moved;
+            row_pos = row_moved;
+        }
+        assert_eq!(Board::placement(pos), RowBoard::placement(row_pos));
+
+        let features = board.1;
+        TetrisBoard::lock(&mut board, pos);
+        TetrisBoard::lock(&mut row_board, row_pos);
+        assert_same_cells(&board, &row_board);
+
+        let mut unlocked = row_board.clone();
+        TetrisBoard::unlock(&mut unlocked, row_pos);
+        assert_eq!(unlocked.1, features);
+
+        let cleared = TetrisBoard::clear_lines(&mut board);
+        assert_eq!(cleared, TetrisBoard::clear_lines(&mut row_board));
+        assert_same_cells(&board, &row_board);
+
+        piece = Piece::PIECES[rng.generate_range(0..7usize)];
+        pos = Board::spawn_pos(piece);
+        row_pos = RowBoard::spawn_pos(piece);
+
+        if board.collides(pos) {
+            assert!(row_board.collides(row_pos));
+            return;
+        }
+
+        check(&board, &row_board, piece);
+    }
+}
+
+#[test]
+fn moves_match() {
+    for seed in 0..GAMES {
+        play(seed, |_, _, _| {});
+    }
+}
+
+#[test]
+fn searches_match() {
+    for seed in 0..GAMES / 4 {
+        play(seed, |board, row_board, piece| {
+            let mut ai = TetrisAi::with_rng(SequenceRng::new(vec![piece]), 19);
+            ai.game.board = board.clone();
+
+            let game = RowGame::with_rng(SequenceRng::new(vec![piece]), 19);
+            let mut row_ai = RowTetrisAi::from_game(game);
+            row_ai.game.board = row_board.clone();
+
+            let cells: HashSet<_> = ai
+                .search()
+                .into_iter()
+                .map(|(pos, _)| Board::placement(pos).cells)
+                .collect();
+            let rows: HashSet<_> = row_ai
+                .search()
+                .into_iter()
+                .map(|pos| RowBoard::placement(pos).cells)
+                .collect();
+
+            assert_eq!(cells, rows, "{piece:?}\n{board}");
+        });
+    }
+}
+
+#[test]
+fn timed_searches_match() {
+    for seed in 0..GAMES / 20 {
+        let level = [18, 19, 29][seed as usize % 3];
+        let profile = InputProfile::ALL[seed as usize % InputProfile::ALL.len()];
+
+        play(seed, |board, row_board, piece| {
+            let mut ai = TetrisAi::with_rng(SequenceRng::new(vec![piece]), level);
+            ai.game.board = board.clone();
+            ai.profile = Some(profile);
+
+            let game = RowGame::with_rng(SequenceRng::new(vec![piece]), level);
+            let mut row_ai = RowTetrisAi::from_game(game);
+            row_ai.game.board = row_board.clone();
+
+            let cells: HashSet<_> = ai
+                .search()
+                .into_iter()
+                .map(|(pos, _)| Board::placement(pos).cells)
+                .collect();
+            let rows: HashSet<_> = row_ai
+                .search_timed(profile)
+                .into_iter()
+                .map(|pos| RowBoard::placement(pos).cells)
+                .collect();
+
+            assert_eq!(
+                cells, rows,
+                "{piece:?} on level {level} with {profile}\n{board}"
+            );
+        });
+    }
+}
+
+#[test]
+fn best_moves_match() {
+    for seed in 0..GAMES / 4 {
+        play(seed, |board, row_board, piece| {
+            let mut ai = TetrisAi::with_rng(SequenceRng::new(vec![piece]), 19);
+            ai.game.board = board.clone();
+
+            let game = RowGame::with_rng(SequenceRng::new(vec![piece]), 19);
+            let mut row_ai = RowTetrisAi::from_game(game);
+            row_ai.game.board = row_board.clone();
+
+            // Ties can be broken differently, so only the scores have to agree.
+            let score = ai.find_best_move().map(|(_, score)| score);
+            let row_score = row_ai.find_best_move().map(|(_, score)| score);
+
+            assert_eq!(score, row_score, "{piece:?}\n{board}");
+        });
+    }
+}
@@ -0,0 +1,410 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use game::tetris_board::{TetrisBoard, FIELD_HEIGHT, FIELD_WIDTH};
+
+/// Declares `EvalWeights` and `EvalFeatures` with a field for every feature, plus the lists that
+/// go over all of them, so the fields only have to be named once. Every feature is written as its
+/// doc comment, name and the doc comment of its weight.
+macro_rules! features {
+    ($($(#[doc = $doc:literal])* $name:ident => $weight_doc:literal,)*) => {
+        /// How many features `eval` weighs.
+        pub const FEATURES: usize = [$(stringify!($name)),*].len();
+
+        /// How much every feature of a board counts in `eval`. The defaults only penalize holes by
+        /// their depth and bumpiness.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct EvalWeights {
+            $(#[doc = $weight_doc] pub $name: u32,)*
+        }
+
+        /// The raw features of a board that `eval` weighs, named like the weights.
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct EvalFeatures {
+            $($(#[doc = $doc])* pub $name: u32,)*
+        }
+
+        impl EvalWeights {
+            pub const ZERO: Self = Self { $($name: 0,)* };
+
+            /// Names of the weights, in the order of `values`, as used in weight files.
+            pub const NAMES: [&'static str; FEATURES] = [$(stringify!($name)),*];
+
+            pub const fn values(&self) -> [u32; FEATURES] {
+                [$(self.$name),*]
+            }
+
+            fn get_mut(&mut self, name: &str) -> Option<&mut u32> {
+                match name {
+                    $(stringify!($name) => Some(&mut self.$name),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl EvalFeatures {
+            /// Values in the order of `EvalWeights::NAMES`.
+            pub const fn values(&self) -> [u32; FEATURES] {
+                [$(self.$name),*]
+            }
+        }
+    };
+}
+
+features! {
+    /// Empty cells below the highest block of their column.
+    holes => "Penalty for every hole.",
+    /// Sum of how deep every hole is below the highest block of its column.
+    hole_depth => "Penalty for every row a hole is below the top of its column.",
+    /// Sum of the height differences between neighbouring columns.
+    bumpiness => "Penalty for every row of height difference between neighbouring columns.",
+    /// Sum of the heights of all columns.
+    aggregate_height => "Penalty for every row of height of every column.",
+    /// Height of the highest column.
+    max_height => "Penalty for every row of height of the highest column.",
+    /// Changes between filled and empty along every row up to the highest block, counting the
+    /// walls as filled.
+    row_transitions => "Penalty for every change between filled and empty along a row.",
+    /// Changes between filled and empty going up every column from the floor to its highest
+    /// block.
+    column_transitions => "Penalty for every change between filled and empty along a column.",
+    /// Sum of how much lower every column is than the lower of its neighbours, or its only one
+    /// next to a wall.
+    well_depth => "Penalty for every row of depth of a well.",
+    /// Blocks above the lowest hole of their column.
+    covered_cells => "Penalty for every block above a hole.",
+    /// 1 when no column has four rows above it that are full everywhere else, so a tetris can't be
+    /// scored right away, otherwise 0.
+    not_tetris_ready => "Penalty for a board where an I piece can't score a tetris right away.",
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        Self {
+            hole_depth: 500,
+            bumpiness: 10,
+            ..Self::ZERO
+        }
+    }
+}
+
+impl EvalWeights {
+    /// Reads weights from a file in the format of `FromStr`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, WeightsError> {
+        std::fs::read_to_string(path)
+            .map_err(WeightsError::Io)?
+            .parse()
+    }
+}
+
+/// Why weights couldn't be loaded.
+#[derive(Debug)]
+pub enum WeightsError {
+    Io(std::io::Error),
+    /// The line with this number, counted from 1, isn't `name = value`.
+    Syntax(usize),
+    UnknownWeight(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't read weights: {err}"),
+            Self::Syntax(line) => write!(f, "line {line} isn't `name = value`"),
+            Self::UnknownWeight(name) => write!(f, "unknown weight {name:?}"),
+            Self::InvalidValue(value) => write!(f, "invalid weight {value:?}"),
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+/// Reads weights written as `name = value` lines, like `Display` writes them. Weights that are
+/// left out keep their default, and everything after a `#` is ignored.
+impl FromStr for EvalWeights {
+    type Err = WeightsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut res = Self::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or(WeightsError::Syntax(i + 1))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            let weight = res
+                .get_mut(name)
+                .ok_or_else(|| WeightsError::UnknownWeight(name.to_owned()))?;
+            *weight = value
+                .parse()
+                .map_err(|_| WeightsError::InvalidValue(value.to_owned()))?;
+        }
+
+        Ok(res)
+    }
+}
+
+impl fmt::Display for EvalWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in Self::NAMES.into_iter().zip(self.values()) {
+            writeln!(f, "{name} = {value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl EvalFeatures {
+    pub fn new<B: TetrisBoard>(board: &B) -> Self {
+        let features = board.features();
+        let heights = features.heights.map(|h| h as usize);
+
+        let mut res = Self {
+            holes: features.total_holes(),
+            aggregate_height: heights.iter().sum::<usize>() as u32,
+            max_height: heights.iter().copied().max().unwrap_or(0) as u32,
+            not_tetris_ready: 1,
+            ..Self::default()
+        };
+
+        for (x, &h) in heights.iter().enumerate() {
+            let mut filled_below = true;
+            let mut lowest_hole = None;
+
+            for y in 0..h {
+                let filled = board.filled(x, y);
+
+                if filled != filled_below {
+                    res.column_transitions += 1;
+                }
+                filled_below = filled;
+
+                if !filled {
+                    res.hole_depth += (h - 1 - y) as u32;
+                    lowest_hole.get_or_insert(y);
+                }
+            }
+
+            if let Some(y) = lowest_hole {
+                res.covered_cells += (h - y) as u32 - features.holes[x] as u32;
+            }
+
+            let left = if x == 0 { usize::MAX } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(usize::MAX);
+            res.well_depth += left.min(right).saturating_sub(h) as u32;
+
+            let mut rows = h..h + 4;
+            if rows.end <= FIELD_HEIGHT && rows.all(|y| features.row_fill[y] == 9) {
+                res.not_tetris_ready = 0;
+            }
+        }
+
+        for h in heights.windows(2) {
+            res.bumpiness += h[0].abs_diff(h[1]) as u32;
+        }
+
+        for y in 0..res.max_height as usize {
+            let mut filled_left = true;
+
+            for x in 0..FIELD_WIDTH {
+                let filled = board.filled(x, y);
+
+                if filled != filled_left {
+                    res.row_transitions += 1;
+                }
+                filled_left = filled;
+            }
+
+            if !filled_left {
+                res.row_transitions += 1;
+            }
+        }
+
+        res
+    }
+}
+
+/// Score of a board where the game is over, for searches that average scores as `f32`. Not less
+/// than anything `eval` returns, so it's worse than any real board.
+pub const TOP_OUT: f32 = u32::MAX as f32;
+
+/// Scores a board, lower is better, by adding up its `EvalFeatures` times `weights`.
+pub fn eval<B: TetrisBoard>(board: &B, weights: &EvalWeights) -> u32 {
+    breakdown(board, weights).total()
+}
+
+/// Every feature `eval` adds up for `board`, to see why it scores like it does.
+pub fn breakdown<B: TetrisBoard>(board: &B, weights: &EvalWeights) -> Breakdown {
+    Breakdown {
+        features: EvalFeatures::new(board),
+        weights: *weights,
+    }
+}
+
+/// The features of a board with the weights they were scored with, see `breakdown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Breakdown {
+    pub features: EvalFeatures,
+    pub weights: EvalWeights,
+}
+
+impl Breakdown {
+    /// How much every feature adds to the score, in the order of `EvalWeights::NAMES`.
+    pub fn contributions(&self) -> [u32; FEATURES] {
+        let features = self.features.values();
+        let weights = self.weights.values();
+
+        std::array::from_fn(|i| features[i].saturating_mul(weights[i]))
+    }
+
+    pub fn total(&self) -> u32 {
+        self.contributions()
+            .into_iter()
+            .fold(0, u32::saturating_add)
+    }
+}
+
+/// A table with the value, weight and contribution of every feature, and the total score.
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = EvalWeights::NAMES
+            .into_iter()
+            .zip(self.features.values())
+            .zip(self.weights.values())
+            .zip(self.contributions());
+
+        for (((name, value), weight), contribution) in rows {
+            writeln!(f, "{name:<18} {value:>4} x {weight:>5} = {contribution:>8}")?;
+        }
+
+        writeln!(f, "{:<18} {:>25}", "total", self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::board::Board;
+
+    use super::*;
+
+    #[test]
+    fn features() {
+        let board: Board = "
+            . . . . . . . . . .
+            . . . . O . . . . .
+            O . . . . . . . . .
+            O O O O O O O O . O
+            O O O O . O O O . O
+            O O O O O O O O . O
+        "
+        .parse()
+        .unwrap();
+
+        let features = EvalFeatures::new(&board);
+
+        assert_eq!(features.holes, 2);
+        assert_eq!(features.hole_depth, 3 + 1);
+        assert_eq!(features.bumpiness, 1 + 2 + 2 + 3 + 3);
+        assert_eq!(features.aggregate_height, 4 + 3 * 7 + 5);
+        assert_eq!(features.max_height, 5);
+        assert_eq!(features.row_transitions, 2 + 4 + 2 + 2 + 4);
+        assert_eq!(features.column_transitions, 4);
+        assert_eq!(features.well_depth, 3);
+        assert_eq!(features.covered_cells, 2);
+        assert_eq!(features.not_tetris_ready, 1);
+    }
+
+    #[test]
+    fn tetris_ready() {
+        let board: Board = "
+            O O O O O O O O O .
+            O O O O O O O O O .
+            O O O O O O O O O .
+            O O O O O O O O O .
+        "
+        .parse()
+        .unwrap();
+
+        let features = EvalFeatures::new(&board);
+
+        assert_eq!(features.not_tetris_ready, 0);
+        assert_eq!(features.well_depth, 4);
+    }
+
+    #[test]
+    fn breakdown_adds_up_to_eval() {
+        let board: Board = "
+            . . . . O . . . . .
+            O O O O . O O O . O
+        "
+        .parse()
+        .unwrap();
+        let weights = EvalWeights {
+            holes: 1000,
+            well_depth: 7,
+            ..Default::default()
+        };
+
+        let breakdown = breakdown(&board, &weights);
+
+        assert_eq!(breakdown.total(), eval(&board, &weights));
+        assert_eq!(breakdown.contributions()[0], 1000);
+        assert_eq!(
+            breakdown.contributions()[7],
+            7 * breakdown.features.well_depth
+        );
+        assert_eq!(
+            breakdown.to_string().lines().count(),
+            EvalWeights::NAMES.len() + 1
+        );
+        assert!(breakdown.to_string().contains("holes"));
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let weights = EvalWeights {
+            holes: 3,
+            not_tetris_ready: 70,
+            ..Default::default()
+        };
+
+        assert_eq!(weights.to_string().parse::<EvalWeights>().unwrap(), weights);
+    }
+
+    #[test]
+    fn parse_weights() {
+        let weights: EvalWeights = "
+            # only holes matter
+            holes = 100
+            hole_depth = 0 # not this
+            bumpiness=0
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            weights,
+            EvalWeights {
+                holes: 100,
+                ..EvalWeights::ZERO
+            }
+        );
+        assert!(matches!(
+            "holse = 1".parse::<EvalWeights>(),
+            Err(WeightsError::UnknownWeight(_))
+        ));
+        assert!(matches!(
+            "holes = -1".parse::<EvalWeights>(),
+            Err(WeightsError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            "\nholes 1".parse::<EvalWeights>(),
+            Err(WeightsError::Syntax(2))
+        ));
+    }
+}
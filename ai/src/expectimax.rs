@@ -0,0 +1,174 @@
+use game::{pieces::Piece, rng::Rng, tetris_board::TetrisBoard};
+
+use crate::{
+    eval::{eval, TOP_OUT},
+    TetrisAi,
+};
+
+/// Settings for `TetrisAi::find_best_move_expectimax`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Expectimax {
+    /// Random pieces to look ahead after the next piece.
+    pub depth: usize,
+    /// Only this many placements of every piece, the ones with the best score right after
+    /// placing them, are searched any deeper.
+    pub width: usize,
+    /// Pieces less likely than this to come next are left out of the average.
+    pub min_probability: f32,
+}
+
+impl Default for Expectimax {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            width: 6,
+            min_probability: 0.01,
+        }
+    }
+}
+
+impl<R: Rng, B: TetrisBoard> TetrisAi<R, B> {
+    /// Like `find_best_move_two_ply`, but after the next piece also places `settings.depth` more
+    /// pieces, averaging over every piece that could come with the probabilities of `rng`. The
+    /// randomizer can only tell those for the piece after next, so they're reused further down.
+    pub fn find_best_move_expectimax(&self, settings: Expectimax) -> Option<(B::Pos, u32)> {
+        let probabilities = self.game.rng.probabilities();
+
+        let mut best: Option<(B::Pos, f32)> = None;
+
+        for candidate in self.candidates(&self.game.board, self.game.pos, settings.width) {
+            let board = &candidate.board;
+            let score = self.expected_score(board, &[self.game.next], settings, &probabilities);
+
+            if best.is_none_or(|(_, best_score)| score < best_score) {
+                best = Some((candidate.pos, score));
+            }
+        }
+
+        best.map(|(pos, score)| (pos, score.min(TOP_OUT) as u32))
+    }
+
+    /// Score `board` can be expected to end up with after placing the `known` pieces and then
+    /// `settings.depth` random ones as well as possible.
+    fn expected_score(
+        &self,
+        board: &B,
+        known: &[Piece],
+        settings: Expectimax,
+        probabilities: &[f32; 7],
+    ) -> f32 {
+        if let [piece, rest @ ..] = known {
+            return self.best_placement(board, *piece, rest, settings, probabilities);
+        }
+
+        if settings.depth == 0 {
+            return eval(board, &self.weights) as f32;
+        }
+
+        let settings = Expectimax {
+            depth: settings.depth - 1,
+            ..settings
+        };
+        let mut total = 0.0;
+        let mut score = 0.0;
+
+        for (piece, &p) in Piece::PIECES.into_iter().zip(probabilities) {
+            if p < settings.min_probability {
+                continue;
+            }
+
+            total += p;
+            score += p * self.best_placement(board, piece, &[], settings, probabilities);
+        }
+
+        match total > 0.0 {
+            true => score / total,
+            false => eval(board, &self.weights) as f32,
+        }
+    }
+
+    /// Best expected score over every placement of `piece` on `board`.
+    fn best_placement(
+        &self,
+        board: &B,
+        piece: Piece,
+        known: &[Piece],
+        settings: Expectimax,
+        probabilities: &[f32; 7],
+    ) -> f32 {
+        let start = B::spawn_pos(piece);
+
+        if board.collides(start) {
+            return TOP_OUT;
+        }
+
+        self.candidates(board, start, settings.width)
+            .into_iter()
+            .map(|candidate| self.expected_score(&candidate.board, known, settings, probabilities))
+            .fold(TOP_OUT, f32::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{board::Board, rng::SevenBag, row_board::RowBoard};
+
+    use super::*;
+
+    #[test]
+    fn without_random_pieces_matches_two_ply() {
+        let settings = Expectimax {
+            depth: 0,
+            width: 100,
+            ..Default::default()
+        };
+
+        crate::assert_matches_two_ply::<Board>(|ai| ai.find_best_move_expectimax(settings));
+        crate::assert_matches_two_ply::<RowBoard>(|ai| ai.find_best_move_expectimax(settings));
+    }
+
+    #[test]
+    fn weighs_pieces_by_probability() {
+        let mut ai = TetrisAi::<SevenBag>::from_seed(5, 19);
+        for _ in 0..6 {
+            ai.game.pos = ai.find_best_move().unwrap().0;
+            ai.game.lock();
+        }
+
+        let settings = Expectimax {
+            depth: 1,
+            width: 100,
+            min_probability: 0.1,
+        };
+        let board = &ai.game.board;
+        let expected =
+            |probabilities: [f32; 7]| ai.expected_score(board, &[], settings, &probabilities);
+        let best = |piece| ai.best_score(board, piece) as f32;
+        let (i, o) = (Piece::I, Piece::O);
+
+        let mut only_i = [0.0; 7];
+        only_i[i as usize] = 1.0;
+        assert_eq!(expected(only_i), best(i));
+
+        let mut mostly_o = [0.0; 7];
+        mostly_o[i as usize] = 0.25;
+        mostly_o[o as usize] = 0.75;
+        assert_eq!(expected(mostly_o), 0.25 * best(i) + 0.75 * best(o));
+
+        // Too unlikely pieces are left out, and the others weighed as if they were all there is.
+        mostly_o[Piece::T as usize] = 0.05;
+        assert_eq!(expected(mostly_o), 0.25 * best(i) + 0.75 * best(o));
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let settings = Expectimax::default();
+
+        crate::assert_plays_a_game::<Board>(30, |ai| {
+            ai.find_best_move_expectimax(settings).map(|(pos, _)| pos)
+        });
+        crate::assert_plays_a_game::<RowBoard>(30, |ai| {
+            ai.find_best_move_expectimax(settings).map(|(pos, _)| pos)
+        });
+    }
+}
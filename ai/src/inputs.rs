@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+
+use game::{
+    rng::Rng,
+    tetris_board::{Placement, TetrisBoard},
+    Game, Input, Inputs,
+};
+
+use crate::{profile::InputProfile, search::Shifting};
+
+/// Inputs held down from `frame` on, until the next change. Frames are counted from the first
+/// frame played after the search started, so the first change is usually at frame 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimedInputs {
+    pub frame: u32,
+    pub inputs: Inputs,
+}
+
+/// Every combination of inputs that can be held on a frame, simplest first.
+const CHOICES: [Inputs; 10] = [
+    Inputs::NONE,
+    Inputs(Input::Down as u8),
+    Inputs(Input::Left as u8),
+    Inputs(Input::Right as u8),
+    Inputs(Input::RotateCW as u8),
+    Inputs(Input::RotateCCW as u8),
+    Inputs(Input::Left as u8 | Input::RotateCW as u8),
+    Inputs(Input::Left as u8 | Input::RotateCCW as u8),
+    Inputs(Input::Right as u8 | Input::RotateCW as u8),
+    Inputs(Input::Right as u8 | Input::RotateCCW as u8),
+];
+
+/// Finds the fastest way to move the current piece of `game` to `target`, by playing every
+/// combination of inputs frame by frame with `Game::step`, so rotation and gravity behave exactly
+/// like they do in the game. Buttons other than soft drop are only ever tapped for a single frame:
+/// tapping every other frame moves faster than DAS. Of the fastest ways, the one that changes
+/// inputs the least is returned. Only the cells of `target` are compared, so the rotation of
+/// pieces with two identical orientations doesn't matter.
+///
+/// The returned inputs end with everything released once the piece is at `target`, which can be
+/// the frame it locks. If `target` is a final position from `search`, gravity then locks the
+/// piece there. Returns `None` when the piece can't get there.
+pub fn find_inputs<R, B>(game: &Game<R, B>, target: Placement) -> Option<Vec<TimedInputs>>
+where
+    R: Rng,
+    B: TetrisBoard,
+{
+    find_inputs_with(game, target, None)
+}
+
+/// Like `find_inputs`, but only presses left and right as fast as a player with `profile` can.
+/// With DAS they are held down instead of tapped.
+pub fn find_profile_inputs<R, B>(
+    game: &Game<R, B>,
+    target: Placement,
+    profile: InputProfile,
+) -> Option<Vec<TimedInputs>>
+where
+    R: Rng,
+    B: TetrisBoard,
+{
+    find_inputs_with(game, target, Some(profile))
+}
+
+fn find_inputs_with<R, B>(
+    game: &Game<R, B>,
+    target: Placement,
+    profile: Option<InputProfile>,
+) -> Option<Vec<TimedInputs>>
+where
+    R: Rng,
+    B: TetrisBoard,
+{
+    /// Frames since left or right was last pressed, the direction and the run, like in the timed
+    /// search.
+    #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+    struct Presses {
+        since: u8,
+        dir: u8,
+        run: u8,
+    }
+
+    struct Node<R, B: TetrisBoard> {
+        game: Game<R, B>,
+        parent: usize,
+        inputs: Inputs,
+        changes: u32,
+        presses: Presses,
+    }
+
+    let shifting = profile.map(InputProfile::shifting);
+    let horizontal = Input::Left as u8 | Input::Right as u8;
+    let holdable = match profile {
+        Some(InputProfile::Das) => Input::Down as u8 | horizontal,
+        _ => Input::Down as u8,
+    };
+
+    let reached = |game: &Game<R, B>| B::placement(game.pos).cells == target.cells;
+
+    if game.entry_delay == 0 && reached(game) {
+        return Some(Vec::new());
+    }
+
+    let presses = Presses {
+        since: shifting.map_or(0, |s| s.max_delay()),
+        dir: 0,
+        run: 0,
+    };
+    let root = Node {
+        game: game.clone(),
+        parent: usize::MAX,
+        inputs: game.held,
+        changes: 0,
+        presses,
+    };
+    let mut nodes = vec![root];
+    let mut layer = vec![0];
+    let mut visited = HashSet::new();
+
+    // Every layer is one frame further, and of the nodes that get to the same state on the same
+    // frame only the one that changed inputs the least is kept.
+    while !layer.is_empty() {
+        let mut next_layer: HashMap<_, usize> = HashMap::new();
+        let mut found: Option<usize> = None;
+
+        for &i in &layer {
+            let held = nodes[i].game.held;
+
+            for inputs in CHOICES {
+                if inputs.0 & held.0 & !holdable != 0 {
+                    continue;
+                }
+
+                let mut presses = nodes[i].presses;
+
+                if let Some(shifting) = shifting {
+                    presses.since = (presses.since + 1).min(shifting.max_delay());
+
+                    let pressed = inputs.pressed_since(held).0 & horizontal;
+                    if pressed != 0 {
+                        let same_dir = pressed == presses.dir;
+
+                        if nodes[i].presses.since < shifting.delay(same_dir, presses.run) {
+                            continue;
+                        }
+
+                        presses = Presses {
+                            since: 1,
+                            dir: if shifting == Shifting::Das {
+                                pressed
+                            } else {
+                                0
+                            },
+                            run: shifting.next_run(same_dir, presses.run),
+                        };
+                    }
+                }
+
+                let mut next = nodes[i].game.clone();
+
+                let locked = next.step(inputs).is_some();
+                let done = reached(&next) && (locked || next.entry_delay == 0);
+
+                if locked && !done || next.state.is_finished() {
+                    continue;
+                }
+
+                let timers = (next.frames_since_drop, next.entry_delay, next.das);
+                let key = (next.pos, next.held, timers, presses);
+                let changes = nodes[i].changes + (inputs != nodes[i].inputs) as u32;
+
+                if visited.contains(&key) {
+                    continue;
+                }
+
+                if let Some(&other) = next_layer.get(&key) {
+                    if nodes[other].changes <= changes {
+                        continue;
+                    }
+                }
+
+                nodes.push(Node {
+                    game: next,
+                    parent: i,
+                    inputs,
+                    changes,
+                    presses,
+                });
+                next_layer.insert(key, nodes.len() - 1);
+
+                if done && found.is_none_or(|f| nodes[f].changes > changes) {
+                    found = Some(nodes.len() - 1);
+                }
+            }
+        }
+
+        if let Some(mut i) = found {
+            let mut frames = Vec::new();
+
+            while i != 0 {
+                frames.push(nodes[i].inputs);
+                i = nodes[i].parent;
+            }
+
+            frames.reverse();
+            return Some(changes(frames));
+        }
+
+        visited.extend(next_layer.keys().copied());
+        layer = next_layer.into_values().collect();
+        layer.sort_unstable();
+    }
+
+    None
+}
+
+/// Turns the inputs held on every frame into the changes between them, followed by a release.
+fn changes(mut frames: Vec<Inputs>) -> Vec<TimedInputs> {
+    frames.push(Inputs::NONE);
+
+    let mut res: Vec<TimedInputs> = Vec::new();
+    for (frame, inputs) in frames.into_iter().enumerate() {
+        if res
+            .last()
+            .map_or(inputs != Inputs::NONE, |last| last.inputs != inputs)
+        {
+            res.push(TimedInputs {
+                frame: frame as u32,
+                inputs,
+            });
+        }
+    }
+
+    res
+}
+
+/// Expands input changes into the inputs held on every frame, for playing them with `Game::step`.
+/// The last inputs are held for one frame.
+pub fn held_per_frame(changes: &[TimedInputs]) -> Vec<Inputs> {
+    let mut res = Vec::new();
+
+    for change in changes {
+        let frame = change.frame as usize;
+        let held = res.last().copied().unwrap_or(Inputs::NONE);
+
+        res.resize(frame, held);
+        res.push(change.inputs);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{
+        board::Board,
+        pieces::{Piece, Rotation},
+        rng::SequenceRng,
+        Level, RowGame,
+    };
+
+    use crate::search::{search, search_timed};
+
+    use super::*;
+
+    /// Plays the inputs found for a spread of the final positions of `game`, and checks the piece
+    /// locks where it was supposed to.
+    fn reach_placements<B: TetrisBoard>(game: Game<SequenceRng, B>) {
+        for pos in search(&game.board, game.pos).into_iter().step_by(3) {
+            let target = B::placement(pos);
+            let changes =
+                find_inputs(&game, target).unwrap_or_else(|| panic!("{target:?} not reachable"));
+
+            let mut game = game.clone();
+            let mut locked = None;
+
+            let frames = held_per_frame(&changes).into_iter();
+
+            for inputs in frames.chain(std::iter::repeat(Inputs::NONE)) {
+                let before = game.pos;
+
+                if game.step(inputs).is_some() {
+                    locked = Some(before);
+                    break;
+                }
+            }
+
+            assert_eq!(
+                locked.map(|pos| B::placement(pos).cells),
+                Some(target.cells)
+            );
+        }
+    }
+
+    #[test]
+    fn reaches_placements() {
+        reach_placements(Game::<_, Board>::with_rng(
+            SequenceRng::new(vec![Piece::S]),
+            18,
+        ));
+        reach_placements(RowGame::with_rng(SequenceRng::new(vec![Piece::T]), 18));
+    }
+
+    #[test]
+    fn tucks_under_overhang() {
+        let game = Game::<_, Board>::from_diagram(
+            "
+            I I I I . . . . . .
+            . . . . . . . . . .
+            . . . . . . . . . .
+            ",
+            SequenceRng::new(vec![Piece::O]),
+            18,
+        )
+        .unwrap();
+
+        let target = Placement::new(Piece::O, Rotation::Right, [(2, 0), (3, 0), (2, 1), (3, 1)]);
+        let changes = find_inputs(&game, target).unwrap();
+
+        assert!(held_per_frame(&changes).contains(&Inputs::from(Input::Left)));
+        assert_eq!(changes.last().unwrap().inputs, Inputs::NONE);
+    }
+
+    #[test]
+    fn soft_drops_to_save_time() {
+        let game = Game::<_, Board>::with_rng(SequenceRng::new(vec![Piece::O]), 8);
+        let target = Placement::new(Piece::O, Rotation::Right, [(4, 0), (5, 0), (4, 1), (5, 1)]);
+
+        let changes = find_inputs(&game, target).unwrap();
+
+        assert_eq!(changes[0].inputs, Input::Down.into());
+        assert_eq!(changes.len(), 2);
+    }
+
+    #[test]
+    fn profiles_reach_timed_placements() {
+        let game = Game::<_, Board>::with_rng(SequenceRng::new(vec![Piece::T]), 29);
+        let speed = Level(29).drop_speed();
+
+        for profile in [
+            InputProfile::Tap12Hz,
+            InputProfile::Das,
+            InputProfile::Rolling,
+        ] {
+            let positions = search_timed(&game.board, game.pos, speed, profile.shifting());
+
+            for pos in positions.into_iter().step_by(4) {
+                let changes = find_profile_inputs(&game, Board::placement(pos), profile);
+                let changes = changes.unwrap_or_else(|| panic!("{profile}: {pos:?} not reachable"));
+
+                if profile == InputProfile::Das {
+                    continue;
+                }
+
+                let taps: Vec<_> = changes
+                    .iter()
+                    .filter(|change| change.inputs.0 & 0b11 != 0)
+                    .map(|change| change.frame)
+                    .collect();
+                let schedule = profile.schedule(3);
+                let gap = schedule.windows(2).map(|w| w[1] - w[0]).min().unwrap();
+
+                assert!(taps.windows(2).all(|w| w[1] - w[0] >= gap));
+            }
+        }
+    }
+
+    #[test]
+    fn das_holds_instead_of_tapping() {
+        // Soft dropping between presses doesn't help on level 19.
+        let game = Game::<_, Board>::with_rng(SequenceRng::new(vec![Piece::O]), 19);
+        let target = Placement::new(Piece::O, Rotation::Right, [(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        let changes = find_profile_inputs(&game, target, InputProfile::Das).unwrap();
+        let frames = held_per_frame(&changes);
+        let left = frames
+            .iter()
+            .filter(|inputs| inputs.contains(Input::Left))
+            .count();
+
+        // 4 shifts: on the press, after charging DAS and twice more every 6 frames.
+        assert_eq!(changes[0].inputs, Input::Left.into());
+        assert_eq!(left, 16 + 6 + 6 + 1);
+        assert!(find_profile_inputs(&game, target, InputProfile::Tap10Hz).is_some());
+    }
+}
@@ -1,244 +1,260 @@
+pub mod beam;
+#[cfg(test)]
+mod differential;
+pub mod eval;
+pub mod expectimax;
 pub mod flatness_states;
+pub mod inputs;
+pub mod mcts;
+pub mod profile;
 pub mod row_ai;
 mod recursive_search;
+pub mod search;
 pub mod states;
 
+use std::fmt;
+
 use arrayvec::ArrayVec;
 
-use game::{
-    board::*,
-    pieces::{Piece, Rotation},
-    rng::*,
-    Level,
+use crate::{
+    eval::{breakdown, eval, Breakdown, EvalWeights},
+    profile::InputProfile,
+    search::{search_reach, search_timed, Reach},
 };
 
+use game::{board::*, pieces::Piece, rng::*, tetris_board::TetrisBoard, Game, Level};
+
+/// Plays a `Game` on either board representation, with every planner written once for both.
 #[derive(Debug, Clone)]
-pub struct TetrisAi<R> {
-    pub board: Board,
-    pub rng: R,
-    pub current: Piece,
-    pub next: Piece,
-    pub pos: PiecePositions,
-    pub rot: Rotation,
-    pub level: Level,
-    pub score: usize,
-    pub highest_blocks: [u8; BOARD_WIDTH],
+pub struct TetrisAi<R, B: TetrisBoard = Board> {
+    pub game: Game<R, B>,
+    pub weights: EvalWeights,
+    /// When set, `search` only returns positions a player with this profile can get to in time
+    /// at this level, see `search_timed`.
+    pub profile: Option<InputProfile>,
 }
 
-impl<R> TetrisAi<R> {
-    pub fn find_best_move(&mut self) -> Option<(PiecePositions, u32)> {
-        let positions = self.search();
-        let mut best_score = u32::MAX;
-        let mut best_pos = None;
+/// A placement of a piece, with the board it leaves behind after clearing lines.
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate<B: TetrisBoard> {
+    pub pos: B::Pos,
+    pub board: B,
+    pub lines: u8,
+    pub score: u32,
+}
 
-        for pos in positions {
-            self.pos = pos;
+impl<R, B: TetrisBoard> TetrisAi<R, B> {
+    pub fn from_game(game: Game<R, B>) -> Self {
+        Self {
+            game,
+            weights: EvalWeights::default(),
+            profile: None,
+        }
+    }
 
-            let highest_blocks_old = self.highest_blocks;
+    /// The position of the current piece that leaves the best board behind, after clearing lines.
+    pub fn find_best_move(&self) -> Option<(B::Pos, u32)> {
+        self.candidates(&self.game.board, self.game.pos, 1)
+            .first()
+            .map(|candidate| (candidate.pos, candidate.score))
+    }
 
-            for p in self.pos {
-                self.board.0[p as usize] = Some(self.current);
+    pub fn holes(&self) -> u64 {
+        self.game.board.features().total_holes() as u64
+    }
 
-                let rem = p % BW;
-                let new = p - rem;
-                if self.highest_blocks[rem as usize] > new {
-                    self.highest_blocks[rem as usize] = new;
-                }
-            }
+    pub fn eval(&self) -> u32 {
+        eval(&self.game.board, &self.weights)
+    }
 
-            let score = self.eval();
+    /// What `eval` is made up of for the current board.
+    pub fn breakdown(&self) -> Breakdown {
+        breakdown(&self.game.board, &self.weights)
+    }
 
-            if score < best_score {
-                best_score = score;
-                best_pos = Some(pos);
-            }
+    /// What `eval` is made up of for the board the current piece leaves behind at `pos`, after
+    /// clearing lines.
+    pub fn breakdown_of(&self, pos: B::Pos) -> Breakdown {
+        breakdown(&self.placed(pos).0, &self.weights)
+    }
 
-            for p in self.pos {
-                self.board.0[p as usize] = None;
-            }
+    /// The `n` best placements of the current piece, best first, with the lines each clears and
+    /// the breakdown of the board it leaves behind.
+    pub fn top_candidates(&self, n: usize) -> Vec<(B::Pos, u8, Breakdown)> {
+        self.candidates(&self.game.board, self.game.pos, n)
+            .into_iter()
+            .map(|candidate| {
+                let breakdown = breakdown(&candidate.board, &self.weights);
+                (candidate.pos, candidate.lines, breakdown)
+            })
+            .collect()
+    }
 
-            self.highest_blocks = highest_blocks_old;
-        }
+    /// The board with the current piece locked at `pos` and lines cleared, and the lines cleared.
+    fn placed(&self, pos: B::Pos) -> (B, u8) {
+        let mut board = self.game.board.clone();
+        board.lock(pos);
+        let lines = board.clear_lines();
 
-        best_pos.map(|p| (p, best_score))
+        (board, lines)
     }
 
-    pub fn holes(&self) -> u64 {
-        let mut holes = 0;
+    /// Like `find_best_move`, but also places the next piece on the board every position of the
+    /// current piece leaves behind, and picks the position that allows the best placement of both.
+    pub fn find_best_move_two_ply(&self) -> Option<(B::Pos, u32)> {
+        let mut best: Option<(B::Pos, u32)> = None;
 
-        for (i, h) in self.highest_blocks.into_iter().enumerate() {
-            let mut offset = BW;
-            while h + offset < BOARD_SIZE_U8 {
-                if self.board.0[(h + offset) as usize + i].is_none() {
-                    holes += 1;
-                }
+        for (pos, _) in self.search() {
+            let mut board = self.game.board.clone();
+            board.lock(pos);
+            board.clear_lines();
 
-                offset += BW;
+            let score = self.best_score(&board, self.game.next);
+
+            if best.is_none_or(|(_, best_score)| score < best_score) {
+                best = Some((pos, score));
             }
         }
 
-        holes
+        best
     }
 
-    pub fn eval(&self) -> u32 {
-        let mut hole_score = 0u32;
-
-        for (i, h) in self.highest_blocks.into_iter().enumerate() {
-            let mut weight = BW;
-            while h + weight < BOARD_SIZE_U8 {
-                if self.board.0[(h + weight) as usize + i].is_none() {
-                    hole_score += weight as u32;
-                }
+    /// Score of the best board `piece` can leave behind when it spawns on `board`, with lines
+    /// cleared. `u32::MAX` when the piece tops out or can't be placed anywhere.
+    fn best_score(&self, board: &B, piece: Piece) -> u32 {
+        let start = B::spawn_pos(piece);
 
-                weight += BW;
-            }
+        if board.collides(start) {
+            return u32::MAX;
         }
 
-        let mut flatness = 0u32;
+        let mut best = u32::MAX;
 
-        for h in self.highest_blocks.windows(3) {
-            let diff = h[0].abs_diff(h[1]) as u32;
-            // println!("diff: {diff}");
-            flatness += diff;
-        }
+        for (pos, _) in self.search_board(board, start) {
+            let mut board = board.clone();
+            board.lock(pos);
+            board.clear_lines();
 
-        // let mut left_weight = 0u32;
+            best = best.min(eval(&board, &self.weights));
+        }
 
-        // for (h, w) in self.highest_blocks.into_iter().zip((0..BOARD_WIDTH as u32).rev()) {
-        //     left_weight += (BOARD_SIZE_U8 - h) as u32 * w;
-        // }
+        best
+    }
 
-        // println!("flatness: {}, hole score: {}", flatness, hole_score.saturating_pow(2));
+    /// The `width` placements of the piece at `start` that leave the best boards right away, best
+    /// first.
+    pub(crate) fn candidates(
+        &self,
+        board: &B,
+        start: B::Pos,
+        width: usize,
+    ) -> ArrayVec<Candidate<B>, 100> {
+        let mut res: ArrayVec<_, 100> = self
+            .search_board(board, start)
+            .into_iter()
+            .map(|(pos, _)| {
+                let mut board = board.clone();
+                board.lock(pos);
+                let lines = board.clear_lines();
+
+                let score = eval(&board, &self.weights);
+                Candidate {
+                    pos,
+                    board,
+                    lines,
+                    score,
+                }
+            })
+            .collect();
 
-        flatness + hole_score * 50
+        res.sort_by_key(|candidate| candidate.score);
+        res.truncate(width);
+        res
     }
 
-    pub fn search(&self) -> ArrayVec<PiecePositions, 100> {
-        use Piece::*;
-        
-        let mut final_states = ArrayVec::new();
-        let mut searched_states = [0u8; BOARD_SIZE];
-        let mut stack = Vec::with_capacity(70);
-        stack.push((self.pos, self.rot));
+    /// Every position the current piece can end up in, and whether it needs soft drop to get
+    /// there.
+    pub fn search(&self) -> ArrayVec<(B::Pos, Reach), 100> {
+        self.search_board(&self.game.board, self.game.pos)
+    }
 
-        while let Some((pos, rot)) = stack.pop() {
+    /// Like `search`, for a piece at `start` on another board.
+    pub fn search_board(&self, board: &B, start: B::Pos) -> ArrayVec<(B::Pos, Reach), 100> {
+        let mut res = search_reach(board, start);
 
-            if let Some(new_pos) = self.board.try_left(pos) {
-                if searched_states[new_pos[0] as usize] == 0 {
-                    searched_states[new_pos[0] as usize] |= rot as u8;
-                    stack.push((new_pos, rot));
-                }
-            }
-    
-            if let Some(new_pos) = self.board.try_right(pos) {
-                if searched_states[new_pos[0] as usize] & rot as u8 == 0 {
-                    searched_states[new_pos[0] as usize] |= rot as u8;
-                    stack.push((new_pos, rot));
-                }
-            }
-    
-            match self.current {
-                I | S | Z => {
-                    if let Some((new_pos, new_rot)) = self.board.try_rot_cw(pos, rot, self.current) {
-                        if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
-                            searched_states[new_pos[0] as usize] |= new_rot as u8;
-                            stack.push((new_pos, new_rot));
-                        }
-                    }
-                },
-                L | J | T => {
-                    if let Some((new_pos, new_rot)) = self.board.try_rot_cw(pos, rot, self.current) {
-                        if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
-                            searched_states[new_pos[0] as usize] |= new_rot as u8;
-                            stack.push((new_pos, new_rot));
-                        }
-                    }
-            
-                    if let Some((new_pos, new_rot)) = self.board.try_rot_ccw(pos, rot, self.current) {
-                        if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
-                            searched_states[new_pos[0] as usize] |= new_rot as u8;
-                            stack.push((new_pos, new_rot));
-                        }
-                    }
-                },
-                O => {}
-            }
-    
-            match self.board.try_down(pos) {
-                Some(new_pos) if searched_states[new_pos[0] as usize] & rot as u8 == 0 => {
-                    searched_states[new_pos[0] as usize] |= rot as u8;
-                    stack.push((new_pos, rot));
-                }
-                None => final_states.push(pos),
-                _ => {}
-            }
+        if let Some(profile) = self.profile {
+            let speed = self.game.level.drop_speed();
+            let timed = search_timed(board, start, speed, profile.shifting());
+            res.retain(|(pos, _)| timed.contains(pos));
         }
 
-        final_states
+        res
+    }
+}
+
+impl<R, B: TetrisBoard + fmt::Display> TetrisAi<R, B> {
+    /// Prints the `n` best placements of the current piece with the board each leaves behind and
+    /// the breakdown of its score, to see why a move was picked over the others.
+    pub fn print_candidates(&self, n: usize) {
+        for (i, (pos, lines, breakdown)) in self.top_candidates(n).into_iter().enumerate() {
+            println!("#{} {:?}, {} lines cleared:", i + 1, pos, lines);
+            println!("{}", self.placed(pos).0);
+            println!("{breakdown}");
+        }
     }
 }
 
-impl<R: Rng> TetrisAi<R> {
+impl<R: Rng, B: TetrisBoard> TetrisAi<R, B> {
     pub fn new(level: impl Into<Level>) -> Self {
-        let mut rng = R::init();
-        let current = rng.next();
+        Self::with_rng(R::init(), level)
+    }
 
-        let level = level.into();
+    /// Creates an ai whose piece sequence only depends on `seed`.
+    pub fn from_seed(seed: u64, level: impl Into<Level>) -> Self {
+        Self::with_rng(R::from_seed(seed), level)
+    }
 
-        Self {
-            board: Board::new(),
-            highest_blocks: [BOARD_SIZE_U8; BOARD_WIDTH],
-            current,
-            next: rng.next(),
-            pos: current.start_pos(),
-            rot: Rotation::Right,
-            level,
-            score: 0,
-            rng,
-        }
+    pub fn with_rng(rng: R, level: impl Into<Level>) -> Self {
+        Self::from_game(Game::with_rng(rng, level))
     }
 
-    pub fn from_board(
-        board: Board,
-        level: impl Into<Level>,
-    ) -> Self {
-        let mut res = Self::new(level);
-        res.highest_blocks = board.find_highest_blocks();
-        res.board = board;
-        res
+    pub fn from_board(board: B, rng: R, level: impl Into<Level>) -> Self {
+        Self::from_game(Game::from_board(board, rng, level))
     }
+}
 
-    pub fn lock(&mut self) {
-        for p in self.pos {
-            self.board.0[p as usize] = Some(self.current);
+/// Plays `moves` pieces from the same start with the moves `pick` chooses, checking every one of
+/// them is a placement `search` finds and that the game doesn't end.
+#[cfg(test)]
+fn assert_plays_a_game<B: TetrisBoard>(
+    moves: usize,
+    mut pick: impl FnMut(&TetrisAi<SevenBag, B>) -> Option<B::Pos>,
+) {
+    let mut ai = TetrisAi::<SevenBag, B>::from_seed(8, 19);
 
-            let rem = p % BW;
-            let new = p - rem;
-            if self.highest_blocks[rem as usize] > new {
-                self.highest_blocks[rem as usize] = new;
-            }
-        }
+    for _ in 0..moves {
+        let pos = pick(&ai).unwrap();
 
-        let mut r = 20;
+        assert!(ai.search().iter().any(|&(found, _)| found == pos));
 
-        while r < BOARD_SIZE {
-            if self.board.0[r..(r + BOARD_WIDTH)]
-                .iter()
-                .all(|p| p.is_some())
-            {
-                for p in (20..r).rev() {
-                    self.board.0[p + BOARD_WIDTH] = self.board.0[p];
-                }
-            }
-            r += 10;
-        }
+        ai.game.pos = pos;
+        ai.game.lock();
+    }
+
+    assert!(!ai.game.state.is_finished());
+}
 
-        self.pos = self.next.start_pos();
-        self.rot = Rotation::Right;
+/// Checks that `find`, which may only look at the current and next piece, scores its move like
+/// `find_best_move_two_ply`.
+#[cfg(test)]
+fn assert_matches_two_ply<B: TetrisBoard>(
+    find: impl Fn(&TetrisAi<SevenBag, B>) -> Option<(B::Pos, u32)>,
+) {
+    let ai = TetrisAi::<SevenBag, B>::from_seed(5, 19);
 
-        self.current = self.next;
-        self.next = self.rng.next();
-    }
+    let score = find(&ai).map(|(_, score)| score);
+
+    assert_eq!(score, ai.find_best_move_two_ply().map(|(_, score)| score));
 }
 
 #[test]
@@ -250,3 +266,122 @@ fn search_l() {
 
     ai.search();
 }
+
+#[test]
+fn features_stay_correct() {
+    let mut ai = TetrisAi::<SevenBag>::from_seed(3, 19);
+
+    for _ in 0..100 {
+        let Some((pos, _)) = ai.find_best_move() else {
+            break;
+        };
+
+        ai.game.pos = pos;
+        ai.game.lock();
+
+        assert_eq!(
+            ai.game.board.1,
+            game::tetris_board::BoardFeatures::new(&ai.game.board)
+        );
+    }
+}
+
+#[test]
+fn two_ply_leaves_the_best_board_for_the_next_piece() {
+    let mut ai = TetrisAi::<SevenBag>::from_seed(11, 19);
+
+    // Every placement of both pieces, scored on the board the second one leaves behind.
+    let brute_force = |ai: &TetrisAi<SevenBag>, pos| {
+        let mut board = ai.game.board.clone();
+        TetrisBoard::lock(&mut board, pos);
+        board.clear_lines();
+
+        let start = Board::spawn_pos(ai.game.next);
+        if board.collides(start) {
+            return u32::MAX;
+        }
+
+        search_reach(&board, start)
+            .into_iter()
+            .map(|(pos, _)| {
+                let mut board = board.clone();
+                TetrisBoard::lock(&mut board, pos);
+                board.clear_lines();
+                eval(&board, &ai.weights)
+            })
+            .min()
+            .unwrap_or(u32::MAX)
+    };
+
+    for _ in 0..20 {
+        let Some((pos, score)) = ai.find_best_move_two_ply() else {
+            break;
+        };
+
+        let best = ai
+            .search()
+            .into_iter()
+            .map(|(pos, _)| brute_force(&ai, pos))
+            .min();
+
+        assert_eq!(Some(score), best);
+        assert_eq!(brute_force(&ai, pos), score);
+
+        ai.game.pos = pos;
+        ai.game.lock();
+    }
+
+    assert!(!ai.game.state.is_finished());
+}
+
+#[test]
+fn scores_match_on_both_boards() {
+    use game::row_board::RowBoard;
+
+    let ai = TetrisAi::<SevenBag>::from_seed(2, 19);
+    let row_ai = TetrisAi::<SevenBag, RowBoard>::from_seed(2, 19);
+
+    // Ties can be broken differently, so only the scores have to agree.
+    fn score<P>(best: Option<(P, u32)>) -> Option<u32> {
+        best.map(|(_, score)| score)
+    }
+
+    assert_eq!(score(ai.find_best_move()), score(row_ai.find_best_move()));
+    assert_eq!(
+        score(ai.find_best_move_two_ply()),
+        score(row_ai.find_best_move_two_ply())
+    );
+}
+
+#[test]
+fn top_candidates_break_down_their_scores() {
+    let board: Board = "
+        . . . . . . . . . .
+        O O O O O O O O O .
+        O O O O O O O O O .
+        O O . O O O O O O .
+        O O O O O O O O O .
+    "
+    .parse()
+    .unwrap();
+    let mut ai = TetrisAi::from_board(board, SequenceRng::new(vec![Piece::I]), 19);
+
+    let top = ai.top_candidates(5);
+    let (best, score) = ai.find_best_move().unwrap();
+
+    assert_eq!(top.len(), 5);
+    assert_eq!((top[0].0, top[0].2.total()), (best, score));
+    // The I clears the three rows that only miss the right column.
+    assert_eq!(top[0].1, 3);
+    assert!(top.windows(2).all(|w| w[0].2.total() <= w[1].2.total()));
+
+    for &(pos, _, breakdown) in &top {
+        assert_eq!(ai.breakdown_of(pos), breakdown);
+    }
+
+    ai.game.pos = best;
+    ai.game.lock();
+    assert_eq!(ai.breakdown(), top[0].2);
+
+    ai.print_candidates(3);
+}
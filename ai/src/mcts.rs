@@ -0,0 +1,322 @@
+use std::time::{Duration, Instant};
+
+use nanorand::{Rng as _, WyRand};
+
+use game::{rng::Rng, tetris_board::TetrisBoard, Level};
+
+use crate::{
+    eval::{self, eval},
+    TetrisAi,
+};
+
+/// When `TetrisAi::find_best_move_mcts` stops searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+/// Settings for `TetrisAi::find_best_move_mcts`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mcts {
+    pub budget: Budget,
+    /// Placements of the current and next piece in the tree, the ones with the best score right
+    /// after placing them.
+    pub width: usize,
+    /// Random pieces played in every rollout, after the current and next piece.
+    pub rollout_depth: usize,
+    /// Placements a rollout picks from at random, best first.
+    pub rollout_width: usize,
+    /// How much visiting rarely tried moves is preferred over the best ones so far.
+    pub exploration: f64,
+    /// Value of the points for clearing lines on level 0, compared to the score of the board.
+    pub points_weight: f64,
+    /// The same seed always picks the same move for the same ai.
+    pub seed: u64,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self {
+            budget: Budget::Iterations(300),
+            width: 8,
+            rollout_depth: 4,
+            rollout_width: 3,
+            exploration: 1.4,
+            points_weight: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Value of a rollout that topped out. `eval` can score a live board as high as `eval::TOP_OUT`,
+/// so this stays below the value of any rollout that survives, whatever the weights are.
+const TOP_OUT: f64 = -2.0 * eval::TOP_OUT as f64;
+
+/// A placement of the current or next piece in the tree.
+struct Node<B: TetrisBoard> {
+    pos: B::Pos,
+    board: B,
+    /// Value of the lines this placement cleared.
+    points: f64,
+    visits: u32,
+    total: f64,
+    /// Placements of the next piece after this one, once they were searched.
+    children: Option<Vec<Node<B>>>,
+}
+
+impl<B: TetrisBoard> Node<B> {
+    fn mean(&self) -> f64 {
+        self.total / self.visits.max(1) as f64
+    }
+}
+
+impl<R: Rng, B: TetrisBoard> TetrisAi<R, B> {
+    /// Picks a move with Monte Carlo tree search. The tree holds the placements of the current
+    /// and next piece, and every iteration continues one of them with a rollout of random pieces
+    /// from a freshly seeded `R`, so the real upcoming pieces stay unknown. A rollout is worth
+    /// the points of the lines it clears minus the score of the board it ends on, and the move
+    /// tried most often wins. Returns the average value of that move.
+    pub fn find_best_move_mcts(&self, settings: Mcts) -> Option<(B::Pos, f64)> {
+        let mut roots = self.nodes(&self.game.board, self.game.pos, settings);
+
+        if roots.is_empty() {
+            return None;
+        }
+
+        let mut rng = WyRand::new_seed(settings.seed);
+        let started = Instant::now();
+        let mut iterations = 0;
+
+        loop {
+            let done = match settings.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(time) => iterations > 0 && started.elapsed() >= time,
+            };
+            if done {
+                break;
+            }
+            iterations += 1;
+
+            let (low, high) = value_range(&roots);
+            let root = select(&mut roots, settings.exploration, low, high);
+
+            let children = root.children.get_or_insert_with(|| {
+                let start = B::spawn_pos(self.game.next);
+
+                match root.board.collides(start) {
+                    true => Vec::new(),
+                    false => self.nodes(&root.board, start, settings),
+                }
+            });
+
+            let value = match children.is_empty() {
+                true => TOP_OUT,
+                false => {
+                    let (low, high) = value_range(children);
+                    let child = select(children, settings.exploration, low, high);
+                    let value = child.points + self.rollout(&child.board, settings, &mut rng);
+
+                    child.visits += 1;
+                    child.total += value;
+                    value
+                }
+            };
+
+            root.visits += 1;
+            root.total += root.points + value;
+        }
+
+        roots
+            .into_iter()
+            .max_by_key(|node| node.visits)
+            .map(|node| (node.pos, node.mean()))
+    }
+
+    /// Tree nodes for the best placements of the piece at `start`.
+    fn nodes(&self, board: &B, start: B::Pos, settings: Mcts) -> Vec<Node<B>> {
+        self.candidates(board, start, settings.width)
+            .into_iter()
+            .map(|candidate| Node {
+                pos: candidate.pos,
+                points: points(candidate.lines, settings),
+                board: candidate.board,
+                visits: 0,
+                total: 0.0,
+                children: None,
+            })
+            .collect()
+    }
+
+    /// Plays random pieces on `board`, each on one of its best placements picked at random.
+    fn rollout(&self, board: &B, settings: Mcts, rng: &mut WyRand) -> f64 {
+        let mut pieces = R::from_seed(rng.generate());
+        let mut board = board.clone();
+        let mut value = 0.0;
+
+        for _ in 0..settings.rollout_depth {
+            let piece = pieces.next();
+            let start = B::spawn_pos(piece);
+
+            if board.collides(start) {
+                return TOP_OUT;
+            }
+
+            let mut candidates = self.candidates(&board, start, settings.rollout_width);
+            if candidates.is_empty() {
+                return TOP_OUT;
+            }
+
+            let candidate = candidates.swap_remove(rng.generate_range(0..candidates.len()));
+            value += points(candidate.lines, settings);
+            board = candidate.board;
+        }
+
+        value - eval(&board, &self.weights) as f64
+    }
+}
+
+fn points(lines: u8, settings: Mcts) -> f64 {
+    Level(0).line_clear_points(lines) as f64 * settings.points_weight
+}
+
+/// Lowest and highest average value of the visited nodes, to scale values for `select`.
+fn value_range<B: TetrisBoard>(nodes: &[Node<B>]) -> (f64, f64) {
+    nodes
+        .iter()
+        .filter(|node| node.visits > 0)
+        .map(Node::mean)
+        .fold((f64::MAX, f64::MIN), |(low, high), mean| {
+            (low.min(mean), high.max(mean))
+        })
+}
+
+/// Picks the node to continue with UCB1, trying every node once first.
+fn select<B: TetrisBoard>(
+    nodes: &mut [Node<B>],
+    exploration: f64,
+    low: f64,
+    high: f64,
+) -> &mut Node<B> {
+    let parent_visits: u32 = nodes.iter().map(|node| node.visits).sum();
+    let log_visits = (parent_visits.max(1) as f64).ln();
+    let range = if high > low { high - low } else { 1.0 };
+
+    let ucb = |node: &Node<B>| match node.visits {
+        0 => f64::INFINITY,
+        visits => {
+            let value = (node.mean() - low) / range;
+            value + exploration * (log_visits / visits as f64).sqrt()
+        }
+    };
+
+    let best = (0..nodes.len())
+        .max_by(|&a, &b| ucb(&nodes[a]).total_cmp(&ucb(&nodes[b])))
+        .unwrap();
+
+    &mut nodes[best]
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{board::Board, pieces::Piece, rng::SevenBag, row_board::RowBoard};
+
+    use crate::eval::EvalWeights;
+
+    use super::*;
+
+    #[test]
+    fn same_seed_same_move() {
+        let ai = TetrisAi::<SevenBag>::from_seed(4, 19);
+        let settings = Mcts {
+            budget: Budget::Iterations(60),
+            ..Default::default()
+        };
+
+        let (pos, _) = ai.find_best_move_mcts(settings).unwrap();
+
+        assert_eq!(ai.find_best_move_mcts(settings).unwrap().0, pos);
+        assert!(ai.search().iter().any(|&(found, _)| found == pos));
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let settings = Mcts {
+            budget: Budget::Iterations(40),
+            ..Default::default()
+        };
+
+        crate::assert_plays_a_game::<Board>(20, |ai| {
+            ai.find_best_move_mcts(settings).map(|(pos, _)| pos)
+        });
+        crate::assert_plays_a_game::<RowBoard>(20, |ai| {
+            ai.find_best_move_mcts(settings).map(|(pos, _)| pos)
+        });
+    }
+
+    #[test]
+    fn more_iterations_pick_better_moves() {
+        // How much worse than the best placement of the current and next piece the picked moves
+        // are, over a few games.
+        let regret = |iterations| {
+            let settings = Mcts {
+                budget: Budget::Iterations(iterations),
+                ..Default::default()
+            };
+
+            (0..8)
+                .map(|seed| {
+                    let ai = TetrisAi::<SevenBag>::from_seed(seed, 19);
+                    let (pos, _) = ai.find_best_move_mcts(settings).unwrap();
+                    let (_, best) = ai.find_best_move_two_ply().unwrap();
+
+                    let mut board = ai.game.board.clone();
+                    TetrisBoard::lock(&mut board, pos);
+                    board.clear_lines();
+
+                    ai.best_score(&board, ai.game.next) - best
+                })
+                .sum::<u32>()
+        };
+
+        assert!(regret(100) < regret(8));
+    }
+
+    #[test]
+    fn topping_out_is_worse_than_any_board() {
+        let row = ". . . O O O O . . .\n";
+        let board: Board = row.repeat(17).parse().unwrap();
+        let mut ai = TetrisAi::from_board(board, SevenBag::from_seed(0), 19);
+        ai.game.pos = Board::spawn_pos(Piece::O);
+        ai.game.next = Piece::O;
+        // Every board scores u32::MAX, the same as a board that topped out in `eval`.
+        ai.weights = EvalWeights {
+            aggregate_height: u32::MAX,
+            ..EvalWeights::ZERO
+        };
+        let settings = Mcts {
+            width: 40,
+            rollout_depth: 0,
+            ..Default::default()
+        };
+
+        let (pos, _) = ai.find_best_move_mcts(settings).unwrap();
+        let mut board = ai.game.board.clone();
+        TetrisBoard::lock(&mut board, pos);
+
+        assert!(!board.collides(Board::spawn_pos(Piece::O)));
+    }
+
+    #[test]
+    fn time_budget() {
+        let ai = TetrisAi::<SevenBag>::from_seed(4, 19);
+        let budget = Budget::Time(Duration::from_millis(20));
+
+        assert!(ai
+            .find_best_move_mcts(Mcts {
+                budget,
+                ..Default::default()
+            })
+            .is_some());
+    }
+}
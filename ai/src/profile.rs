@@ -0,0 +1,132 @@
+use std::{fmt, str::FromStr};
+
+use game::Frames;
+
+use crate::search::Shifting;
+
+/// How fast a human player can move pieces sideways, used to only pick moves such a player could
+/// actually make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputProfile {
+    /// Holding left or right and letting DAS do the rest.
+    Das,
+    Tap10Hz,
+    Tap12Hz,
+    Tap15Hz,
+    Tap20Hz,
+    /// A press every other frame, as fast as the game can register taps.
+    Tap30Hz,
+    /// Rolling the fingers over the back of the controller, alternating 2 and 3 frames between
+    /// taps for about 24 taps a second.
+    Rolling,
+}
+
+impl InputProfile {
+    pub const ALL: [Self; 7] = [
+        Self::Das,
+        Self::Tap10Hz,
+        Self::Tap12Hz,
+        Self::Tap15Hz,
+        Self::Tap20Hz,
+        Self::Tap30Hz,
+        Self::Rolling,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Das => "das",
+            Self::Tap10Hz => "10hz",
+            Self::Tap12Hz => "12hz",
+            Self::Tap15Hz => "15hz",
+            Self::Tap20Hz => "20hz",
+            Self::Tap30Hz => "30hz",
+            Self::Rolling => "rolling",
+        }
+    }
+
+    /// How the search should model shifting for this profile.
+    pub const fn shifting(self) -> Shifting {
+        match self {
+            Self::Das => Shifting::Das,
+            Self::Tap10Hz => Shifting::Tap(Frames(6)),
+            Self::Tap12Hz => Shifting::Tap(Frames(5)),
+            Self::Tap15Hz => Shifting::Tap(Frames(4)),
+            Self::Tap20Hz => Shifting::Tap(Frames(3)),
+            Self::Tap30Hz => Shifting::Tap(Frames(2)),
+            Self::Rolling => Shifting::Roll,
+        }
+    }
+
+    /// Frames on which the first `shifts` shifts in one direction happen, counted from the first.
+    pub fn schedule(self, shifts: usize) -> Vec<u32> {
+        let shifting = self.shifting();
+        let mut res = Vec::with_capacity(shifts);
+        let mut frame = 0;
+        let mut run = 0;
+
+        for i in 0..shifts {
+            if i > 0 {
+                frame += shifting.delay(true, run) as u32;
+            }
+
+            res.push(frame);
+            run = shifting.next_run(i > 0, run);
+        }
+
+        res
+    }
+}
+
+impl fmt::Display for InputProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The name of an input profile wasn't recognized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownProfile(pub String);
+
+impl fmt::Display for UnknownProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown input profile {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownProfile {}
+
+impl FromStr for InputProfile {
+    type Err = UnknownProfile;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name() == name)
+            .ok_or_else(|| UnknownProfile(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules() {
+        assert_eq!(InputProfile::Das.schedule(4), [0, 16, 22, 28]);
+        assert_eq!(InputProfile::Tap12Hz.schedule(4), [0, 5, 10, 15]);
+        assert_eq!(InputProfile::Tap30Hz.schedule(3), [0, 2, 4]);
+        assert_eq!(InputProfile::Rolling.schedule(5), [0, 2, 5, 7, 10]);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for profile in InputProfile::ALL {
+            assert_eq!(profile.to_string().parse(), Ok(profile));
+        }
+
+        assert_eq!("12Hz".parse(), Ok(InputProfile::Tap12Hz));
+        assert!("13hz".parse::<InputProfile>().is_err());
+    }
+}
@@ -10,8 +10,8 @@ impl<R> TetrisAi<R> {
         let mut searched_states = [0u8; BOARD_SIZE];
 
         self.search_helper(
-            self.pos,
-            self.rot,
+            self.game.pos.cells,
+            self.game.pos.rot,
             &mut searched_states,
             &mut final_states,
         );
@@ -28,27 +28,27 @@ impl<R> TetrisAi<R> {
     ) {
         use Piece::*;
 
-        if let Some(new_pos) = self.board.try_left(pos) {
+        if let Some(new_pos) = self.game.board.try_left(pos) {
             if searched_states[new_pos[0] as usize] == 0 {
                 searched_states[new_pos[0] as usize] |= rot as u8;
                 self.search_helper(new_pos, rot, searched_states, final_states)
             }
         }
 
-        if let Some(new_pos) = self.board.try_right(pos) {
+        if let Some(new_pos) = self.game.board.try_right(pos) {
             if searched_states[new_pos[0] as usize] & rot as u8 == 0 {
                 searched_states[new_pos[0] as usize] |= rot as u8;
                 self.search_helper(new_pos, rot, searched_states, final_states)
             }
         }
 
-        match self.current {
+        match self.game.current() {
             I | S | Z => self.search_only_cw(pos, rot, searched_states, final_states),
             L | J | T => self.search_cw_ccw(pos, rot, searched_states, final_states),
             O => {}
         }
 
-        match self.board.try_down(pos) {
+        match self.game.board.try_down(pos) {
             Some(new_pos) if searched_states[new_pos[0] as usize] & rot as u8 == 0 => {
                 searched_states[new_pos[0] as usize] |= rot as u8;
                 self.search_helper(new_pos, rot, searched_states, final_states)
@@ -66,7 +66,9 @@ impl<R> TetrisAi<R> {
         searched_states: &mut [u8; BOARD_SIZE],
         final_states: &mut ArrayVec<PiecePositions, 100>,
     ) {
-        if let Some((new_pos, new_rot)) = self.board.try_rot_cw(pos, rot, self.current) {
+        let piece = self.game.current();
+
+        if let Some((new_pos, new_rot)) = self.game.board.try_rot_cw(pos, rot, piece) {
             if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
                 searched_states[new_pos[0] as usize] |= new_rot as u8;
                 self.search_helper(new_pos, new_rot, searched_states, final_states)
@@ -82,14 +84,16 @@ impl<R> TetrisAi<R> {
         searched_states: &mut [u8; BOARD_SIZE],
         final_states: &mut ArrayVec<PiecePositions, 100>,
     ) {
-        if let Some((new_pos, new_rot)) = self.board.try_rot_cw(pos, rot, self.current) {
+        let piece = self.game.current();
+
+        if let Some((new_pos, new_rot)) = self.game.board.try_rot_cw(pos, rot, piece) {
             if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
                 searched_states[new_pos[0] as usize] |= new_rot as u8;
                 self.search_helper(new_pos, new_rot, searched_states, final_states)
             }
         }
 
-        if let Some((new_pos, new_rot)) = self.board.try_rot_ccw(pos, rot, self.current) {
+        if let Some((new_pos, new_rot)) = self.game.board.try_rot_ccw(pos, rot, piece) {
             if searched_states[new_pos[0] as usize] & new_rot as u8 == 0 {
                 searched_states[new_pos[0] as usize] |= new_rot as u8;
                 self.search_helper(new_pos, new_rot, searched_states, final_states)
@@ -1,50 +1,44 @@
 use arrayvec::ArrayVec;
 
-use game::{
-    pieces::Piece,
-    rng::*,
-    row_board::*,
-    Frames, Level, RowGame,
+use crate::{
+    eval::{Breakdown, EvalFeatures, EvalWeights},
+    profile::InputProfile,
+    search::{search, search_timed},
 };
 
+use game::{pieces::Piece, rng::*, row_board::*, tetris_board::FIELD_HEIGHT, Level, RowGame};
+
+/// The cells of a row inside the walls.
+const FIELD: u16 = !BOUNDS;
+
 #[derive(Debug, Clone)]
 pub struct RowTetrisAi<R> {
     pub game: RowGame<R>,
-    pub input_speed: Frames,
-    pub highest_blocks: [u8; 10],
+    pub weights: EvalWeights,
+    /// When set, `search` and `find_best_move` only consider positions a player with this profile
+    /// can get to in time at this level, see `search_timed`.
+    pub profile: Option<InputProfile>,
 }
 
 impl<R> RowTetrisAi<R> {
-    pub fn from_game(game: RowGame<R>, input_speed: impl Into<Frames>) -> Self {
-        let highest_blocks = game.board.find_highest_blocks();
-        let input_speed = input_speed.into();
-
+    pub fn from_game(game: RowGame<R>) -> Self {
         Self {
             game,
-            input_speed,
-            highest_blocks,
+            weights: EvalWeights::default(),
+            profile: None,
         }
     }
 
     pub fn find_best_move(&mut self) -> Option<(PiecePos, u32)> {
-        let positions = self.search_recursive();
+        let positions = match self.profile {
+            Some(profile) => self.search_timed(profile),
+            None => self.search_recursive(),
+        };
         let mut best_score = u32::MAX;
         let mut best_pos = None;
 
         for pos in positions {
-            self.game.pos = pos;
-            let masks = pos.get_masks();
-
-            let highest_blocks_old = self.highest_blocks;
-
-            for i in 0..4 {
-                let y = self.game.pos.y as usize - i;
-                self.game.board.0[y] |= masks[3 - i];
-
-                // if self.highest_blocks[self.game.pos.x] > p {
-                //     self.highest_blocks[self.game.pos.x] = p;
-                // }
-            }
+            let undo = self.game.board.place(pos);
 
             let score = self.eval();
 
@@ -53,137 +47,39 @@ impl<R> RowTetrisAi<R> {
                 best_pos = Some(pos);
             }
 
-            for i in 0..4 {
-                let y = self.game.pos.y as usize - i;
-                self.game.board.0[y] ^= masks[3 - i];
-
-                // if self.highest_blocks[(p % 10) as usize] > p {
-                //     self.highest_blocks[(p % 10) as usize] = p;
-                // }
-            }
-
-            self.highest_blocks = highest_blocks_old;
+            self.game.board.undo(undo);
         }
 
         best_pos.map(|p| (p, best_score))
     }
 
+    /// Like `TetrisAi::eval`, but works out the features straight from the rows, see
+    /// `row_features`.
     pub fn eval(&self) -> u32 {
-        // let mut hole_score = 0u32;
+        let features = row_features(&self.game.board);
 
-        // for mut h in self.highest_blocks {
-        //     h += BW;
-        //     while h < 10_U8 {
-        //         if self.game.board.0[h as usize].is_none() {
-        //             hole_score += 2;
-        //         }
-
-        //         h += BW;
-        //     }
-        // }
-
-        // let mut flatness = 0u32;
-
-        // for h in self.highest_blocks.windows(2) {
-        //     let diff = h[0].abs_diff(h[1]) as u32;
-        //     // println!("diff: {diff}");
-        //     flatness += diff;
-        // }
-
-        // // let mut left_weight = 0u32;
-
-        // // for (h, w) in self.highest_blocks.into_iter().zip((0..BOARD_WIDTH as u32).rev()) {
-        // //     left_weight += (10_U8 - h) as u32 * w;
-        // // }
-
-        // // println!("flatness: {}, hole score: {}", flatness, hole_score.saturating_pow(2));
-
-        // flatness + hole_score.saturating_pow(2) * 1000
-
-        0
+        Breakdown {
+            features,
+            weights: self.weights,
+        }
+        .total()
     }
 
+    /// Every position the current piece can end up in, only the ones reachable in time when
+    /// `profile` is set.
     pub fn search(&self) -> ArrayVec<PiecePos, 100> {
-        use Piece::*;
-        
-        let mut final_states = ArrayVec::new();
-        let mut searched_states = [0u8; BOARD_HEIGHT * 10];
-        let mut stack = Vec::with_capacity(70);
-        stack.push(self.game.pos);
-
-        while let Some(pos) = stack.pop() {
-
-            if let Some(new_pos) = self.game.board.try_left(pos) {
-                let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                if searched_i < searched_states.len()
-                    && searched_states[searched_i] & new_pos.rot as u8 == 0
-                {
-                    searched_states[searched_i] |= new_pos.rot as u8;
-                    stack.push(new_pos)
-                }
-            }
-    
-            if let Some(new_pos) = self.game.board.try_right(pos) {
-                let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                if searched_i < searched_states.len()
-                    && searched_states[searched_i] & new_pos.rot as u8 == 0
-                {
-                    searched_states[searched_i] |= new_pos.rot as u8;
-                    stack.push(new_pos)
-                }
-            }
-    
-            match self.game.pos.piece {
-                I | S | Z => {
-                    if let Some(new_pos) = self.game.board.try_rot_cw(pos) {
-                        let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                        if searched_i < searched_states.len()
-                            && searched_states[searched_i] & new_pos.rot as u8 == 0
-                        {
-                            searched_states[searched_i] |= new_pos.rot as u8;
-                            stack.push(new_pos)
-                        }
-                    }
-                },
-                L | J | T => {
-                    if let Some(new_pos) = self.game.board.try_rot_cw(pos) {
-                        let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                        if searched_i < searched_states.len()
-                            && searched_states[searched_i] & new_pos.rot as u8 == 0
-                        {
-                            searched_states[searched_i] |= new_pos.rot as u8;
-                            stack.push(new_pos)
-                        }
-                    }
-            
-                    if let Some(new_pos) = self.game.board.try_rot_ccw(pos) {
-                        let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                        if searched_i < searched_states.len()
-                            && searched_states[searched_i] & new_pos.rot as u8 == 0
-                        {
-                            searched_states[searched_i] |= new_pos.rot as u8;
-                            stack.push(new_pos)
-                        }
-                    }
-                },
-                O => {}
-            }
-    
-            match self.game.board.try_down(pos) {
-                Some(new_pos) => {
-                    let searched_i = new_pos.y as usize * 10 + new_pos.x as usize;
-                    if searched_i < searched_states.len()
-                        && searched_states[searched_i] & new_pos.rot as u8 == 0
-                    {
-                        searched_states[searched_i] |= new_pos.rot as u8;
-                        stack.push(new_pos)
-                    }
-                }
-                None => final_states.push(pos),
-            }
+        match self.profile {
+            Some(profile) => self.search_timed(profile),
+            None => search(&self.game.board, self.game.pos),
         }
+    }
 
-        final_states
+    /// Positions the current piece can get to before it locks with `profile`, at the drop speed of
+    /// the current level.
+    pub fn search_timed(&self, profile: InputProfile) -> ArrayVec<PiecePos, 100> {
+        let speed = self.game.level.drop_speed();
+
+        search_timed(&self.game.board, self.game.pos, speed, profile.shifting())
     }
 
     pub fn search_recursive(&self) -> ArrayVec<PiecePos, 100> {
@@ -291,27 +187,104 @@ impl<R> RowTetrisAi<R> {
 }
 
 impl<R: Rng> RowTetrisAi<R> {
-    pub fn new(input_speed: impl Into<Frames>, level: impl Into<Level>) -> Self {
-        Self {
-            game: RowGame::new(level),
-            input_speed: input_speed.into(),
-            highest_blocks: [BOARD_HEIGHT as u8; 10],
+    pub fn new(level: impl Into<Level>) -> Self {
+        Self::with_rng(R::init(), level)
+    }
+
+    /// Creates an ai whose piece sequence only depends on `seed`.
+    pub fn from_seed(seed: u64, level: impl Into<Level>) -> Self {
+        Self::with_rng(R::from_seed(seed), level)
+    }
+
+    pub fn with_rng(rng: R, level: impl Into<Level>) -> Self {
+        Self::from_game(RowGame::with_rng(rng, level))
+    }
+
+    pub fn from_board(board: RowBoard, rng: R, level: impl Into<Level>) -> Self {
+        Self::from_game(RowGame::from_board(board, rng, level))
+    }
+}
+
+/// The same features as `EvalFeatures::new`, going down the rows once with a few bit operations
+/// per row instead of looking at every cell.
+pub fn row_features(board: &RowBoard) -> EvalFeatures {
+    let rows = &board.0;
+    let mut res = EvalFeatures::default();
+
+    // Columns with a block in or above the current row.
+    let mut covered = 0u16;
+    let mut heights = [0u32; 10];
+    let mut top = [0usize; 10];
+    let mut holes = [0u32; 10];
+    let mut lowest_hole = [0usize; 10];
+    // Bit `y` is set when row `y`, counted from the floor, has 9 cells filled.
+    let mut nine_filled = 0u32;
+
+    for r in 0..=MAX_Y as usize {
+        let row = rows[r] & FIELD;
+        let y = MAX_Y as usize - r;
+
+        for x in bits(row & !covered) {
+            heights[x] = y as u32 + 1;
+            top[x] = r;
+        }
+        covered |= row;
+
+        if covered == 0 {
+            continue;
+        }
+
+        let empty = covered & !row;
+        for x in bits(empty) {
+            holes[x] += 1;
+            lowest_hole[x] = r;
+            res.hole_depth += (r - top[x]) as u32;
+        }
+        res.holes += empty.count_ones();
+
+        // Comparing every cell with the one below, the floor counting as filled.
+        res.column_transitions += ((row ^ rows[r + 1]) & covered).count_ones();
+        // Comparing every cell with the one to its right, and the left wall with the first one.
+        res.row_transitions += ((rows[r] ^ (rows[r] >> 1)) & 0b0001_1111_1111_1100).count_ones();
+
+        if row.count_ones() == 9 {
+            nine_filled |= 1 << y;
         }
     }
 
-    pub fn from_board(
-        board: RowBoard,
-        input_speed: impl Into<Frames>,
-        level: impl Into<Level>,
-    ) -> Self {
-        let highest_pieces = board.find_highest_blocks();
+    res.not_tetris_ready = 1;
 
-        Self {
-            game: RowGame::from_board(board, level),
-            input_speed: input_speed.into(),
-            highest_blocks: highest_pieces,
+    for x in 0..10 {
+        let h = heights[x];
+
+        if holes[x] > 0 {
+            res.covered_cells += (lowest_hole[x] - top[x]) as u32 + 1 - holes[x];
+        }
+
+        let left = if x == 0 { u32::MAX } else { heights[x - 1] };
+        let right = heights.get(x + 1).copied().unwrap_or(u32::MAX);
+        res.well_depth += left.min(right).saturating_sub(h);
+
+        if h as usize + 4 <= FIELD_HEIGHT && (nine_filled >> h) & 0b1111 == 0b1111 {
+            res.not_tetris_ready = 0;
         }
     }
+
+    res.aggregate_height = heights.iter().sum();
+    res.max_height = heights.iter().copied().max().unwrap_or(0);
+    res.bumpiness = heights.windows(2).map(|h| h[0].abs_diff(h[1])).sum();
+
+    res
+}
+
+/// Columns of the set bits of a row.
+fn bits(mut row: u16) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        let bit = row.checked_ilog2()?;
+        row &= !(1 << bit);
+
+        Some(12 - bit as usize)
+    })
 }
 
 // #[test]
@@ -322,3 +295,96 @@ impl<R: Rng> RowTetrisAi<R> {
 
 //     ai.search();
 // }
+
+#[cfg(test)]
+mod tests {
+    use game::{board::Board, rng::SevenBag};
+
+    use super::*;
+    use crate::TetrisAi;
+
+    #[test]
+    fn row_features_match_eval_features() {
+        let board: RowBoard = "
+            . . . . . . . . . .
+            . . . . O . . . . .
+            O . . . . . . . . .
+            O O O O O O O O . O
+            O O O O . O O O . O
+            O O O O O O O O . O
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(row_features(&board), EvalFeatures::new(&board));
+        assert_eq!(
+            row_features(&RowBoard::new()),
+            EvalFeatures::new(&RowBoard::new())
+        );
+
+        let mut ai = TetrisAi::<SevenBag>::from_seed(3, 19);
+        // Only keeping the board flat leaves plenty of holes around.
+        ai.weights = EvalWeights {
+            bumpiness: 1,
+            ..EvalWeights::ZERO
+        };
+
+        for _ in 0..40 {
+            let Some((pos, _)) = ai.find_best_move() else {
+                break;
+            };
+            ai.game.pos = pos;
+            ai.game.lock();
+
+            let board: &Board = &ai.game.board;
+            let row_board = RowBoard::from(board);
+
+            assert_eq!(
+                row_features(&row_board),
+                EvalFeatures::new(board),
+                "\n{board}"
+            );
+        }
+    }
+
+    #[test]
+    fn find_best_move_clears_lines() {
+        let mut ai = RowTetrisAi::<SevenBag>::from_seed(1, 19);
+        ai.game.board = "
+            O O O O O O O O O .
+            O O O O O O O O O .
+            O O O O O O O O O .
+            O O O O O O O O O .
+        "
+        .parse()
+        .unwrap();
+        ai.game.pos = Piece::I.row_start_pos();
+        let before = ai.game.board.clone();
+
+        let (pos, score) = ai.find_best_move().unwrap();
+
+        assert_eq!(score, 0);
+        assert_eq!(ai.game.pos, Piece::I.row_start_pos());
+        assert_eq!(ai.game.board.0, before.0);
+        assert_eq!(ai.game.board.1, before.1);
+
+        ai.game.pos = pos;
+        assert_eq!(ai.game.lock(), 4);
+        assert_eq!(ai.game.board.0, RowBoard::new().0);
+    }
+
+    #[test]
+    fn eval_matches_tetris_ai() {
+        let mut ai = RowTetrisAi::<SevenBag>::from_seed(1, 19);
+        ai.game.board = "
+            . . . O . . . . . .
+            O O O O . O O . . O
+            O O . O O O O O . O
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(ai.eval(), crate::eval::eval(&ai.game.board, &ai.weights));
+        assert!(ai.eval() > 0);
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::{HashMap, VecDeque};
+
+use arrayvec::ArrayVec;
+
+use game::{
+    pieces::Piece,
+    tetris_board::{TetrisBoard, MAX_STATES},
+    Frames, DAS_CHARGE, DAS_REPEAT,
+};
+
+/// How a final position from `search_reach` can be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Reach {
+    /// By shifting and rotating the piece where it spawned, then letting it fall straight down.
+    Gravity,
+    /// Only by shifting or rotating the piece after it fell further, like tucks and spins, which
+    /// usually needs soft drop to get there in time.
+    SoftDrop,
+}
+
+/// Finds every position the piece at `start` can come to rest in, moving it left, right, down and
+/// rotating it in any order.
+pub fn search<B: TetrisBoard>(board: &B, start: B::Pos) -> ArrayVec<B::Pos, 100> {
+    use Piece::*;
+
+    let mut final_states = ArrayVec::new();
+    let mut searched_states = [0u8; MAX_STATES];
+    let mut stack = Vec::with_capacity(70);
+    stack.push(start);
+
+    let mut visit = |pos: B::Pos, stack: &mut Vec<B::Pos>| {
+        let i = B::state_index(pos);
+        let rot = B::rotation(pos) as u8;
+
+        if searched_states[i] & rot == 0 {
+            searched_states[i] |= rot;
+            stack.push(pos);
+        }
+    };
+
+    while let Some(pos) = stack.pop() {
+        if let Some(new_pos) = board.try_left(pos) {
+            visit(new_pos, &mut stack);
+        }
+
+        if let Some(new_pos) = board.try_right(pos) {
+            visit(new_pos, &mut stack);
+        }
+
+        match B::piece(pos) {
+            I | S | Z => {
+                if let Some(new_pos) = board.try_rot_cw(pos) {
+                    visit(new_pos, &mut stack);
+                }
+            }
+            L | J | T => {
+                if let Some(new_pos) = board.try_rot_cw(pos) {
+                    visit(new_pos, &mut stack);
+                }
+
+                if let Some(new_pos) = board.try_rot_ccw(pos) {
+                    visit(new_pos, &mut stack);
+                }
+            }
+            O => {}
+        }
+
+        match board.try_down(pos) {
+            Some(new_pos) => visit(new_pos, &mut stack),
+            None => final_states.push(pos),
+        }
+    }
+
+    final_states
+}
+
+/// Like `search`, but also tells how every position can be reached.
+pub fn search_reach<B: TetrisBoard>(board: &B, start: B::Pos) -> ArrayVec<(B::Pos, Reach), 100> {
+    let drops = drops(board, start);
+
+    search(board, start)
+        .into_iter()
+        .map(|pos| match drops.contains(&pos) {
+            true => (pos, Reach::Gravity),
+            false => (pos, Reach::SoftDrop),
+        })
+        .collect()
+}
+
+/// Final positions of the piece when only shifting and rotating it at the height of `start`, and
+/// then dropping it.
+fn drops<B: TetrisBoard>(board: &B, start: B::Pos) -> ArrayVec<B::Pos, 100> {
+    let mut res = ArrayVec::new();
+    let mut searched_states = [0u8; MAX_STATES];
+    let mut stack = vec![start];
+
+    while let Some(pos) = stack.pop() {
+        let moves = [
+            board.try_left(pos),
+            board.try_right(pos),
+            board.try_rot_cw(pos),
+            board.try_rot_ccw(pos),
+        ];
+
+        for new_pos in moves.into_iter().flatten() {
+            let i = B::state_index(new_pos);
+            let rot = B::rotation(new_pos) as u8;
+
+            if searched_states[i] & rot == 0 {
+                searched_states[i] |= rot;
+                stack.push(new_pos);
+            }
+        }
+
+        let mut dropped = pos;
+        while let Some(new_pos) = board.try_down(dropped) {
+            dropped = new_pos;
+        }
+
+        if !res.contains(&dropped) {
+            res.push(dropped);
+        }
+    }
+
+    res
+}
+
+/// How fast a player can shift the piece sideways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shifting {
+    /// Tapping left or right, at most once every so many frames. `Frames(6)` is 10Hz tapping and
+    /// `Frames(2)`, a press every other frame, is as fast as the game allows.
+    Tap(Frames),
+    /// Tapping alternately 2 and 3 frames apart, like when rolling.
+    Roll,
+    /// Holding left or right and letting DAS repeat the shift.
+    Das,
+}
+
+impl Shifting {
+    /// Frames that need to pass after a shift before the next one. `run` is what `next_run`
+    /// returned for the last shift, and `same_dir` whether the next one goes the same way.
+    pub(crate) fn delay(self, same_dir: bool, run: u8) -> u8 {
+        match self {
+            Shifting::Tap(Frames(frames)) => frames,
+            Shifting::Roll if run == 1 => 2,
+            Shifting::Roll => 3,
+            Shifting::Das if !same_dir => 1,
+            Shifting::Das if run == 1 => DAS_CHARGE,
+            Shifting::Das => DAS_REPEAT,
+        }
+    }
+
+    /// What needs to be remembered about the shifts so far after another one, given `run` from
+    /// the last shift: whether it was the first one in its direction for DAS, and whether it was
+    /// an odd one for rolling.
+    pub(crate) fn next_run(self, same_dir: bool, run: u8) -> u8 {
+        match self {
+            Shifting::Tap(_) => 0,
+            Shifting::Roll if run == 1 => 2,
+            Shifting::Roll => 1,
+            Shifting::Das if same_dir => 2,
+            Shifting::Das => 1,
+        }
+    }
+
+    /// Longest delay between two shifts, after which waiting longer makes no difference.
+    pub(crate) fn max_delay(self) -> u8 {
+        match self {
+            Shifting::Tap(Frames(frames)) => frames,
+            Shifting::Roll => 3,
+            Shifting::Das => DAS_CHARGE,
+        }
+    }
+}
+
+/// A piece in the middle of a timed search, before the inputs of a frame.
+#[derive(Debug, Clone, Copy)]
+struct Timed<P> {
+    pos: P,
+    frames_since_drop: u8,
+    /// Frames since the last shift, up to `Shifting::max_delay`.
+    since_shift: u8,
+    /// Direction of the last shift, 0 for none, 1 for left and 2 for right.
+    dir: u8,
+    /// See `Shifting::next_run`.
+    run: u8,
+    /// Rotation button pressed on the last frame, 0 for none, 1 for clockwise and 2 for
+    /// counterclockwise. It needs to be released before it can rotate again, but the other one can
+    /// be pressed right away.
+    rotation: u8,
+}
+
+/// Like `search`, but plays the piece frame by frame: gravity pulls it down every `drop_speed`
+/// frames, while the player shifts it as fast as `shifting` allows and presses each rotation
+/// button at most every other frame. Only returns positions that can be reached before the piece
+/// locks, which matters a lot on high levels. Soft drop is left out, since it only leaves less
+/// time to get somewhere.
+pub fn search_timed<B: TetrisBoard>(
+    board: &B,
+    start: B::Pos,
+    drop_speed: Frames,
+    shifting: Shifting,
+) -> ArrayVec<B::Pos, 100> {
+    use Piece::*;
+
+    let mut final_states = ArrayVec::new();
+    // Without soft drop every position and `frames_since_drop` belong to a single frame, so only
+    // the state that shifted longest ago needs searching. States are searched frame by frame, and
+    // skipped when a better one came along after they were queued.
+    let mut searched = HashMap::new();
+
+    let start = Timed {
+        pos: start,
+        frames_since_drop: 0,
+        since_shift: shifting.max_delay(),
+        dir: 0,
+        run: 0,
+        rotation: 0,
+    };
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(state) = queue.pop_front() {
+        let key = (
+            state.pos,
+            state.frames_since_drop,
+            state.dir,
+            state.run,
+            state.rotation,
+        );
+        if searched
+            .get(&key)
+            .is_some_and(|&since_shift| since_shift > state.since_shift)
+        {
+            continue;
+        }
+
+        for dir in 0..3 {
+            let same_dir = dir == state.dir;
+
+            let mut shifted = state;
+            shifted.since_shift = (state.since_shift + 1).min(shifting.max_delay());
+
+            if dir != 0 {
+                if state.since_shift < shifting.delay(same_dir, state.run) {
+                    continue;
+                }
+
+                let new_pos = match dir {
+                    1 => board.try_left(state.pos),
+                    _ => board.try_right(state.pos),
+                };
+                let Some(new_pos) = new_pos else {
+                    continue;
+                };
+
+                shifted.pos = new_pos;
+                shifted.since_shift = 1;
+
+                shifted.run = shifting.next_run(same_dir, state.run);
+                if shifting == Shifting::Das {
+                    shifted.dir = dir;
+                }
+            }
+
+            let mut rotations = ArrayVec::<_, 3>::new();
+            rotations.push((shifted.pos, 0));
+
+            let cw = (state.rotation != 1)
+                .then(|| board.try_rot_cw(shifted.pos).map(|pos| (pos, 1)))
+                .flatten();
+            let ccw = (state.rotation != 2)
+                .then(|| board.try_rot_ccw(shifted.pos).map(|pos| (pos, 2)))
+                .flatten();
+
+            // Both buttons turn I, S and Z pieces the same way, so only one of them is tried.
+            let turns = match B::piece(shifted.pos) {
+                I | S | Z => [cw.or(ccw), None],
+                L | J | T => [cw, ccw],
+                O => [None, None],
+            };
+            rotations.extend(turns.into_iter().flatten());
+
+            for (pos, rotation) in rotations {
+                let mut next = Timed {
+                    pos,
+                    rotation,
+                    ..shifted
+                };
+                next.frames_since_drop += 1;
+
+                if next.frames_since_drop >= drop_speed.0 {
+                    next.frames_since_drop = 0;
+
+                    match board.try_down(pos) {
+                        Some(new_pos) => next.pos = new_pos,
+                        None => {
+                            if !final_states.contains(&pos) {
+                                final_states.push(pos);
+                            }
+                            continue;
+                        }
+                    }
+                }
+
+                let key = (
+                    next.pos,
+                    next.frames_since_drop,
+                    next.dir,
+                    next.run,
+                    next.rotation,
+                );
+                if searched
+                    .get(&key)
+                    .is_some_and(|&since_shift| since_shift >= next.since_shift)
+                {
+                    continue;
+                }
+
+                searched.insert(key, next.since_shift);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    final_states
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{board::Board, pieces::Piece, rng::SequenceRng, row_board::RowBoard, Game, Level};
+
+    use crate::inputs::find_inputs;
+
+    use super::*;
+
+    #[test]
+    fn tucks_need_soft_drop() {
+        let diagram = "
+            . . . . . . . . . .
+            . . . . . . . . . .
+            . . . . . . . . . .
+            I I I I . . . . . .
+            . . . . . . . . . .
+            . . . . . . . . . .
+        ";
+
+        fn reaches<B: TetrisBoard>(board: &B) -> Vec<([game::tetris_board::Cell; 4], Reach)> {
+            let mut res: Vec<_> = search_reach(board, B::spawn_pos(Piece::O))
+                .into_iter()
+                .map(|(pos, reach)| (B::placement(pos).cells, reach))
+                .collect();
+            res.sort();
+            res
+        }
+
+        let board: Board = diagram.parse().unwrap();
+        let found = reaches(&board);
+
+        assert_eq!(found.len(), 9 + 4);
+        assert!(found.contains(&([(2, 0), (2, 1), (3, 0), (3, 1)], Reach::SoftDrop)));
+        assert!(found.contains(&([(2, 3), (2, 4), (3, 3), (3, 4)], Reach::Gravity)));
+        assert_eq!(
+            found.iter().filter(|(_, r)| *r == Reach::Gravity).count(),
+            9
+        );
+        assert_eq!(reaches(&diagram.parse::<RowBoard>().unwrap()), found);
+    }
+
+    #[test]
+    fn high_gravity_limits_reach() {
+        let board = Board::new();
+        let start = Board::spawn_pos(Piece::I);
+        let all = search(&board, start);
+
+        let timed =
+            |level: u8, shifting| search_timed(&board, start, Level(level).drop_speed(), shifting);
+
+        assert_eq!(timed(18, Shifting::Tap(Frames(2))).len(), all.len());
+        assert_eq!(timed(18, Shifting::Das).len(), all.len());
+
+        let fast = timed(29, Shifting::Tap(Frames(2)));
+        let slow = timed(29, Shifting::Tap(Frames(6)));
+        let das = timed(29, Shifting::Das);
+
+        assert!(fast.iter().all(|pos| all.contains(pos)));
+        assert!(slow.iter().all(|pos| fast.contains(pos)));
+        assert!(slow.len() < fast.len());
+        assert!(das.len() < slow.len());
+
+        // The game itself can get everywhere the fastest tapping can.
+        let game = Game::<_, Board>::with_rng(SequenceRng::new(vec![Piece::I]), 29);
+        for pos in fast {
+            assert!(find_inputs(&game, Board::placement(pos)).is_some());
+        }
+    }
+
+    #[test]
+    fn rotations_alternate_buttons() {
+        let mut game = Game::<_, Board>::with_rng(SequenceRng::new(vec![Piece::T]), 29);
+        game.board.fill(3, 12, Piece::O);
+        game.board.fill(4, 15, Piece::O);
+        let shifting = Shifting::Tap(Frames(2));
+
+        // Tucked under the block at (4, 15) by turning clockwise and then counterclockwise on the
+        // very next frame, before gravity takes it past.
+        let target = [(3, 13), (4, 12), (4, 13), (4, 14)];
+        let timed = search_timed(&game.board, game.pos, Level(29).drop_speed(), shifting);
+        let pos = timed
+            .into_iter()
+            .find(|&pos| Board::placement(pos).cells == target)
+            .unwrap();
+
+        assert!(find_inputs(&game, Board::placement(pos)).is_some());
+    }
+}
@@ -16,9 +16,7 @@ pub fn bench_search_l(c: &mut Criterion) {
 }
 
 pub fn bench_search_row_l(c: &mut Criterion) {
-    let mut ai = ai::row_ai::RowTetrisAi::<game::rng::ClassicRng>::new(1, 19);
-
-    ai.game.pos = game::pieces::Piece::L.row_start_pos();
+    let ai = ai::row_ai::RowTetrisAi::with_rng(util::scripted(game::pieces::Piece::L), 19);
 
     c.bench_function("search rows with L", |b| {
         b.iter(|| {
@@ -44,7 +42,7 @@ pub fn bench_try_left(c: &mut Criterion) {
     board.down();
 
     c.bench_function("try left L", |b| {
-        b.iter(|| board.board.try_left(black_box(board.pos)));
+        b.iter(|| board.board.try_left(black_box(board.pos.cells)));
     });
 }
 
@@ -55,7 +53,7 @@ pub fn bench_try_right(c: &mut Criterion) {
     board.down();
 
     c.bench_function("try right L", |b| {
-        b.iter(|| board.board.try_right(black_box(board.pos)));
+        b.iter(|| board.board.try_right(black_box(board.pos.cells)));
     });
 }
 
@@ -66,7 +64,7 @@ pub fn bench_try_down(c: &mut Criterion) {
     board.down();
 
     c.bench_function("try down L", |b| {
-        b.iter(|| board.board.try_down(black_box(board.pos)));
+        b.iter(|| board.board.try_down(black_box(board.pos.cells)));
     });
 }
 
@@ -78,7 +76,13 @@ pub fn bench_try_rot_cw(c: &mut Criterion) {
     board.down();
 
     c.bench_function("try rotating L clockwise", |b| {
-        b.iter(|| board.board.try_rot_cw(black_box(board.pos), black_box(board.rot), black_box(piece)));
+        b.iter(|| {
+            board.board.try_rot_cw(
+                black_box(board.pos.cells),
+                black_box(board.pos.rot),
+                black_box(piece),
+            )
+        });
     });
 }
 
@@ -90,12 +94,18 @@ pub fn bench_try_rot_ccw(c: &mut Criterion) {
     board.down();
 
     c.bench_function("try rotating L counter clockwise", |b| {
-        b.iter(|| board.board.try_rot_ccw(black_box(board.pos), black_box(board.rot), black_box(piece)));
+        b.iter(|| {
+            board.board.try_rot_ccw(
+                black_box(board.pos.cells),
+                black_box(board.pos.rot),
+                black_box(piece),
+            )
+        });
     });
 }
 
 pub fn bench_find_best_move(c: &mut Criterion) {
-    let mut ai = ai::TetrisAi::<game::rng::ClassicRng>::new(19);
+    let ai = ai::TetrisAi::<game::rng::ClassicRng>::new(19);
 
     c.bench_function("find best move in empty board", |b| {
         b.iter(|| ai.find_best_move());
@@ -1,23 +1,20 @@
 use ai::TetrisAi;
-use game::{pieces::Piece, rng::ClassicRng, Game};
+use game::{
+    pieces::Piece,
+    rng::{ClassicRng, Rng, ScriptedRng},
+    Game,
+};
 
-pub fn game_with_start_piece(piece: Piece, level: u8) -> Game<ClassicRng> {
-    let mut game = Game::<ClassicRng>::new(level);
+pub type BenchRng = ScriptedRng<ClassicRng>;
 
-    game.current = piece;
-    game.pos = game.current.start_pos();
-
-    game
+pub fn scripted(piece: Piece) -> BenchRng {
+    ScriptedRng::new([piece], ClassicRng::init())
 }
 
-pub fn tetris_ai_with_start_piece(
-    piece: Piece,
-    level: u8,
-) -> TetrisAi<ClassicRng> {
-    let mut ai = TetrisAi::<ClassicRng>::new(level);
-
-    ai.current = piece;
-    ai.pos = ai.current.start_pos();
+pub fn game_with_start_piece(piece: Piece, level: u8) -> Game<BenchRng> {
+    Game::with_rng(scripted(piece), level)
+}
 
-    ai
+pub fn tetris_ai_with_start_piece(piece: Piece, level: u8) -> TetrisAi<BenchRng> {
+    TetrisAi::with_rng(scripted(piece), level)
 }
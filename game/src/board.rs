@@ -1,4 +1,4 @@
-use crate::{consts::*, pieces::*};
+use crate::{consts::*, pieces::*, tetris_board::*};
 
 pub const BOARD_SIZE: usize = 220;
 pub const ACTUAL_BOARD_SIZE: usize = u8::MAX as usize + 11;
@@ -9,15 +9,36 @@ pub const BW: u8 = BOARD_WIDTH as u8;
 pub type Pos = u8;
 pub type PiecePositions = [Pos; 4];
 
+/// Position of a piece on a `Board`: the cells it covers, plus what is needed to rotate it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardPos {
+    pub cells: PiecePositions,
+    pub rot: Rotation,
+    pub piece: Piece,
+}
+
+impl BoardPos {
+    pub const fn new(cells: PiecePositions, rot: Rotation, piece: Piece) -> Self {
+        Self { cells, rot, piece }
+    }
+}
+
+/// Cells of the board, and its features. Changing the cells directly leaves the features out of
+/// date, so prefer `lock`, `unlock` and `TetrisBoard::fill`.
 #[derive(Clone, Debug)]
-pub struct Board(pub [Option<Piece>; ACTUAL_BOARD_SIZE]);
+pub struct Board(pub [Option<Piece>; ACTUAL_BOARD_SIZE], pub BoardFeatures);
 
 impl Default for Board {
     fn default() -> Self {
-        Self([None; ACTUAL_BOARD_SIZE])
+        Self([None; ACTUAL_BOARD_SIZE], BoardFeatures::EMPTY)
     }
 }
 
+/// Column and row from the floor of the cell at index `p`.
+const fn cell(p: Pos) -> Cell {
+    (p % BW, (BOARD_SIZE_U8 / BW) - 1 - p / BW)
+}
+
 impl Board {
     pub fn new() -> Self {
         Self::default()
@@ -177,6 +198,7 @@ impl Board {
     pub fn lock(&mut self, pos: PiecePositions, piece: Piece) {
         for p in pos {
             self.0[p as usize] = Some(piece);
+            self.1.add(cell(p));
         }
     }
 
@@ -184,6 +206,42 @@ impl Board {
         for p in pos {
             self.0[p as usize] = None;
         }
+
+        let mut features = self.1;
+        for p in pos {
+            features.remove(self, cell(p));
+        }
+        self.1 = features;
+    }
+
+    /// Removes all full rows in the visible part of the board, moving everything above them down.
+    /// Returns the amount of rows cleared.
+    pub fn clear_lines(&mut self) -> u8 {
+        let mut cleared = 0;
+        let mut rows = 0;
+        let mut write = BOARD_SIZE;
+
+        for read in (0..BOARD_SIZE).step_by(BOARD_WIDTH).rev() {
+            let row = read..(read + BOARD_WIDTH);
+
+            if read >= 2 * BOARD_WIDTH && self.0[row.clone()].iter().all(|p| p.is_some()) {
+                cleared += 1;
+                rows |= 1 << cell(read as u8).1;
+                continue;
+            }
+
+            write -= BOARD_WIDTH;
+            if write != read {
+                self.0.copy_within(row, write);
+            }
+        }
+
+        self.0[..write].fill(None);
+        let mut features = self.1;
+        features.clear(self, rows);
+        self.1 = features;
+
+        cleared
     }
 
     pub fn find_highest_blocks(&self) -> [u8; BOARD_WIDTH] {
@@ -201,6 +259,145 @@ impl Board {
     }
 }
 
+impl TetrisBoard for Board {
+    type Pos = BoardPos;
+
+    fn spawn_pos(piece: Piece) -> BoardPos {
+        BoardPos::new(piece.start_pos(), Rotation::Right, piece)
+    }
+
+    fn piece(pos: BoardPos) -> Piece {
+        pos.piece
+    }
+
+    fn rotation(pos: BoardPos) -> Rotation {
+        pos.rot
+    }
+
+    fn placement(pos: BoardPos) -> Placement {
+        Placement::new(pos.piece, pos.rot, pos.cells.map(cell))
+    }
+
+    fn from_placement(placement: Placement) -> BoardPos {
+        // The order of the cells matters for rotating, so start from the spawn position turned
+        // into the right rotation and move that over.
+        let board = Board::new();
+        let mut pos = Self::spawn_pos(placement.piece);
+        while pos.rot != placement.rot {
+            pos = TetrisBoard::try_rot_cw(&board, pos).expect("piece can rotate at spawn");
+        }
+
+        let rows = (BOARD_SIZE / BOARD_WIDTH) as i16;
+        let (x0, y0) = Self::placement(pos).cells[0];
+        let (x1, y1) = placement.cells[0];
+        assert!(
+            placement.cells.iter().all(|&(_, y)| (y as i16) < rows),
+            "placement is above the board"
+        );
+
+        let offset = x1 as i16 - x0 as i16 + (y0 as i16 - y1 as i16) * BW as i16;
+        let cells = pos.cells.map(|p| (p as i16 + offset) as u8);
+
+        BoardPos { cells, ..pos }
+    }
+
+    #[inline]
+    fn state_index(pos: BoardPos) -> usize {
+        pos.cells[0] as usize
+    }
+
+    #[inline]
+    fn collides(&self, pos: BoardPos) -> bool {
+        pos.cells.iter().any(|&p| self.0[p as usize].is_some())
+    }
+
+    #[inline]
+    fn try_up(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_up(self, pos.cells).map(|cells| BoardPos { cells, ..pos })
+    }
+
+    #[inline]
+    fn try_down(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_down(self, pos.cells).map(|cells| BoardPos { cells, ..pos })
+    }
+
+    #[inline]
+    fn try_left(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_left(self, pos.cells).map(|cells| BoardPos { cells, ..pos })
+    }
+
+    #[inline]
+    fn try_right(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_right(self, pos.cells).map(|cells| BoardPos { cells, ..pos })
+    }
+
+    #[inline]
+    fn try_rot_cw(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_rot_cw(self, pos.cells, pos.rot, pos.piece).map(|(cells, rot)| BoardPos {
+            cells,
+            rot,
+            ..pos
+        })
+    }
+
+    #[inline]
+    fn try_rot_ccw(&self, pos: BoardPos) -> Option<BoardPos> {
+        Board::try_rot_ccw(self, pos.cells, pos.rot, pos.piece).map(|(cells, rot)| BoardPos {
+            cells,
+            rot,
+            ..pos
+        })
+    }
+
+    fn lock(&mut self, pos: BoardPos) {
+        Board::lock(self, pos.cells, pos.piece)
+    }
+
+    fn unlock(&mut self, pos: BoardPos) {
+        Board::unlock(self, pos.cells)
+    }
+
+    fn clear_lines(&mut self) -> u8 {
+        Board::clear_lines(self)
+    }
+
+    #[inline]
+    fn filled(&self, x: usize, y: usize) -> bool {
+        let rows = BOARD_SIZE / BOARD_WIDTH;
+
+        y < rows && self.0[(rows - 1 - y) * BOARD_WIDTH + x].is_some()
+    }
+
+    fn fill(&mut self, x: usize, y: usize, piece: Piece) {
+        if self.filled(x, y) {
+            return;
+        }
+
+        let rows = BOARD_SIZE / BOARD_WIDTH;
+
+        self.0[(rows - 1 - y) * BOARD_WIDTH + x] = Some(piece);
+        self.1.add((x as u8, y as u8));
+    }
+
+    fn features(&self) -> &BoardFeatures {
+        &self.1
+    }
+
+    fn refresh_features(&mut self) {
+        self.1 = BoardFeatures::new(self);
+    }
+}
+
+/// Reads a diagram like the one `Display` prints, see `parse_diagram`. An active piece in the
+/// diagram is left out.
+impl std::str::FromStr for Board {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_diagram(s).map(|(board, _)| board)
+    }
+}
+
 impl std::fmt::Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for i in 2 * BOARD_WIDTH..BOARD_SIZE {
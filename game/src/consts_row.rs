@@ -10,7 +10,7 @@ pub const J_LEFT: u64 = 246292752105472;
 pub const J_UP: u64 = 4611756388782178304;
 pub const O_ALL: u64 = 13835269161514696704;
 pub const T_RIGHT: u64 = 4611932309032009728;
-pub const T_DOWN: u64 = 4611897126807404544;
+pub const T_DOWN: u64 = 4611897125733662720;
 pub const T_LEFT: u64 = 246291678363648;
 pub const T_UP: u64 = 4611791572617396224;
 pub const S_RIGHT_LEFT: u64 = 13835163608398430208;
@@ -78,7 +78,7 @@ mod tests {
             (
                 (
                     0,
-                    0b0001000000000000,
+                    0b0000100000000000,
                     0b0001100000000000,
                     0b0000100000000000,
                 ),
@@ -127,10 +127,7 @@ mod tests {
             // let versions = (14 - (r1 | r2 | r3 | r4).count_ones()) as u64;
 
             let mask = (r4 << 48) + (r3 << 32) + (r2 << 16) + r1;
-            println!(
-                "pub const {}: u64 = {mask};",
-                apply_to.to_uppercase()
-            );
+            println!("pub const {}: u64 = {mask};", apply_to.to_uppercase());
 
             // for offset in 0..versions {
             //     let mut mask = (r4 << 48) + (r3 << 32) + (r2 << 16) + r1;
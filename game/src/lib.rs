@@ -4,18 +4,92 @@ pub mod consts_row;
 pub mod pieces;
 pub mod rng;
 pub mod row_board;
+pub mod tetris_board;
 
 use crate::board::*;
 use crate::pieces::*;
 use crate::rng::*;
 use crate::row_board::*;
+use crate::tetris_board::*;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Input {
-    Left,
-    Right,
-    RotateCW,
-    RotateCCW,
+    Left = 0b1,
+    Right = 0b10,
+    RotateCW = 0b100,
+    RotateCCW = 0b1000,
+    /// Soft drop. Only works while neither `Left` nor `Right` is held, and stops shifting.
+    Down = 0b10000,
+}
+
+/// Set of inputs held down during a single frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Inputs(pub u8);
+
+impl Inputs {
+    pub const NONE: Self = Self(0);
+
+    pub const fn contains(&self, input: Input) -> bool {
+        self.0 & input as u8 != 0
+    }
+
+    pub fn insert(&mut self, input: Input) {
+        self.0 |= input as u8
+    }
+
+    /// Inputs held in `self` that weren't held in `previous`, i.e. newly pressed this frame.
+    pub const fn pressed_since(&self, previous: Inputs) -> Self {
+        Self(self.0 & !previous.0)
+    }
+}
+
+impl From<Input> for Inputs {
+    fn from(value: Input) -> Self {
+        Self(value as u8)
+    }
+}
+
+impl<const N: usize> From<[Input; N]> for Inputs {
+    fn from(value: [Input; N]) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl FromIterator<Input> for Inputs {
+    fn from_iter<T: IntoIterator<Item = Input>>(iter: T) -> Self {
+        let mut res = Self::NONE;
+        iter.into_iter().for_each(|i| res.insert(i));
+        res
+    }
+}
+
+/// Frames a horizontal input needs to be held before it starts auto repeating.
+pub const DAS_CHARGE: u8 = 16;
+/// Frames between auto repeated horizontal movements once DAS is charged.
+pub const DAS_REPEAT: u8 = 6;
+/// Frames between rows moved down while soft dropping.
+pub const SOFT_DROP_SPEED: u8 = 2;
+/// Minimum amount of frames the line clear animation takes.
+pub const LINE_CLEAR_DELAY: u8 = 17;
+
+/// Points for locking a piece while soft dropping, after it was soft dropped `rows` rows without
+/// interruption. Like on the NES, the first row is free.
+pub const fn pushdown_points(rows: u8) -> usize {
+    if rows >= 2 {
+        rows as usize - 1
+    } else {
+        0
+    }
+}
+
+/// Entry delay (ARE) before the next piece spawns, depending on how many rows above the floor the
+/// lowest block of the locked piece is. This is 10 frames for the bottom two rows and goes up by
+/// 2 frames for every 4 rows above that, up to 18 frames.
+pub const fn entry_delay(rows_from_bottom: u8) -> Frames {
+    let extra = (rows_from_bottom as u16 + 2) / 4;
+    let delay = if extra > 4 { 18 } else { 10 + 2 * extra as u8 };
+
+    Frames(delay)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -32,6 +106,48 @@ impl Level {
         self.0 += 1
     }
 
+    /// Lines needed for the first level transition when starting on this level. After that the
+    /// level goes up every 10 lines.
+    pub const fn first_transition(&self) -> usize {
+        let start = self.0 as usize;
+        let late = if start * 10 > 150 {
+            start * 10 - 50
+        } else {
+            100
+        };
+
+        if start * 10 + 10 < late {
+            start * 10 + 10
+        } else {
+            late
+        }
+    }
+
+    /// The level reached after clearing `lines` lines in total, treating `self` as the start level.
+    pub const fn after_lines(&self, lines: usize) -> Self {
+        let first = self.first_transition();
+
+        if lines < first {
+            *self
+        } else {
+            let transitions = 1 + (lines - first) / 10;
+            Self(self.0.wrapping_add(transitions as u8))
+        }
+    }
+
+    /// Points for clearing `lines` lines at once on this level.
+    pub const fn line_clear_points(&self, lines: u8) -> usize {
+        let base = match lines {
+            1 => 40,
+            2 => 100,
+            3 => 300,
+            4 => 1200,
+            _ => 0,
+        };
+
+        base * (self.0 as usize + 1)
+    }
+
     pub fn drop_speed(&self) -> Frames {
         let speed = match self.0 {
             0 => 48,
@@ -64,32 +180,78 @@ impl<T: Into<u8>> From<T> for Frames {
     }
 }
 
+/// Final result of a finished game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Outcome {
+    pub score: usize,
+    pub lines: usize,
+    pub level: Level,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "score: {}, lines: {}, level: {}",
+            self.score, self.lines, self.level.0
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    Playing,
+    /// The game topped out: a new piece spawned overlapping blocks already on the board.
+    Finished(Outcome),
+}
+
+impl GameState {
+    pub const fn is_finished(&self) -> bool {
+        matches!(self, Self::Finished(_))
+    }
+}
+
+/// A game on either board representation. `RowGame` is the same game on a `RowBoard`.
 #[derive(Debug, Clone)]
-pub struct Game<R> {
-    pub board: Board,
-    pub current: Piece,
+pub struct Game<R, B: TetrisBoard = Board> {
+    pub board: B,
     pub next: Piece,
-    pub pos: PiecePositions,
-    pub rot: Rotation,
+    pub pos: B::Pos,
+    pub start_level: Level,
     pub level: Level,
     pub drop_speed: Frames,
     pub score: usize,
+    pub lines: usize,
     pub rng: R,
-    // pub frames_since_last_move: u32,
-    // pub frames_since_drop: u8,
-    // pub finished: bool,
+    pub frame: u32,
+    pub frames_since_drop: u8,
+    pub das: u8,
+    pub held: Inputs,
+    /// Rows the current piece has been soft dropped without interruption.
+    pub pushdown: u8,
+    pub entry_delay: u8,
+    pub state: GameState,
 }
 
-impl<R: Rng> Game<R> {
+pub type RowGame<R> = Game<R, RowBoard>;
+
+impl<R: Rng, B: TetrisBoard> Game<R, B> {
     pub fn new(level: impl Into<Level>) -> Self {
-        let mut rng = R::init();
+        Self::with_rng(R::init(), level)
+    }
+
+    /// Starts a game whose piece sequence only depends on `seed`.
+    pub fn from_seed(seed: u64, level: impl Into<Level>) -> Self {
+        Self::with_rng(R::from_seed(seed), level)
+    }
+
+    pub fn with_rng(mut rng: R, level: impl Into<Level>) -> Self {
         let current = rng.next();
         let next = rng.next();
 
-        let rot = Rotation::Right;
-        let pos = current.start_pos();
+        let pos = B::spawn_pos(current);
 
-        let board = Board::new();
+        let board = B::default();
 
         let level = level.into();
         let drop_speed = level.drop_speed();
@@ -97,24 +259,63 @@ impl<R: Rng> Game<R> {
 
         Self {
             board,
-            current,
             next,
             pos,
-            rot,
+            start_level: level,
             level,
             drop_speed,
             score,
+            lines: 0,
             rng,
+            frame: 0,
+            frames_since_drop: 0,
+            das: 0,
+            held: Inputs::NONE,
+            pushdown: 0,
+            entry_delay: 0,
+            state: GameState::Playing,
         }
     }
 
-    pub fn from_board(board: Board, level: impl Into<Level>) -> Self {
-        let mut res = Self::new(level);
+    /// Starts a game on `board` with the pieces of `rng`, so a position from a bug report plays out
+    /// the same every time when `rng` is seeded. The features of `board` are computed again, in
+    /// case its cells were written to directly.
+    pub fn from_board(mut board: B, rng: R, level: impl Into<Level>) -> Self {
+        board.refresh_features();
+
+        let mut res = Self::with_rng(rng, level);
         res.board = board;
         res
     }
 
-    pub fn up(&mut self) -> Option<PiecePositions> {
+    /// Creates a game on a board read from a diagram, see `parse_diagram`. If the diagram shows an
+    /// active piece, it replaces the first piece.
+    pub fn from_diagram(
+        diagram: &str,
+        rng: R,
+        level: impl Into<Level>,
+    ) -> Result<Self, ParseBoardError> {
+        let (board, pos) = parse_diagram(diagram)?;
+        let mut res = Self::from_board(board, rng, level);
+
+        if let Some(pos) = pos {
+            res.pos = pos;
+        }
+
+        Ok(res)
+    }
+}
+
+impl<R, B: TetrisBoard> Game<R, B> {
+    pub fn current(&self) -> Piece {
+        B::piece(self.pos)
+    }
+
+    pub fn rot(&self) -> Rotation {
+        B::rotation(self.pos)
+    }
+
+    pub fn up(&mut self) -> Option<B::Pos> {
         match self.board.try_up(self.pos) {
             Some(pos) => {
                 self.pos = pos;
@@ -125,7 +326,7 @@ impl<R: Rng> Game<R> {
         }
     }
 
-    pub fn down(&mut self) -> Option<PiecePositions> {
+    pub fn down(&mut self) -> Option<B::Pos> {
         match self.board.try_down(self.pos) {
             Some(pos) => {
                 self.pos = pos;
@@ -136,7 +337,7 @@ impl<R: Rng> Game<R> {
         }
     }
 
-    pub fn left(&mut self) -> Option<PiecePositions> {
+    pub fn left(&mut self) -> Option<B::Pos> {
         match self.board.try_left(self.pos) {
             Some(pos) => {
                 self.pos = pos;
@@ -147,7 +348,7 @@ impl<R: Rng> Game<R> {
         }
     }
 
-    pub fn right(&mut self) -> Option<PiecePositions> {
+    pub fn right(&mut self) -> Option<B::Pos> {
         match self.board.try_right(self.pos) {
             Some(pos) => {
                 self.pos = pos;
@@ -158,90 +359,201 @@ impl<R: Rng> Game<R> {
         }
     }
 
-    pub fn rot_cw(&mut self) -> Option<(PiecePositions, Rotation)> {
-        match self.board.try_rot_cw(self.pos, self.rot, self.current) {
-            Some((pos, rot)) => {
+    pub fn rot_cw(&mut self) -> Option<B::Pos> {
+        match self.board.try_rot_cw(self.pos) {
+            Some(pos) => {
                 self.pos = pos;
-                self.rot = rot;
 
-                Some((pos, rot))
+                Some(pos)
             }
             None => None,
         }
     }
 
-    pub fn rot_ccw(&mut self) -> Option<(PiecePositions, Rotation)> {
-        match self.board.try_rot_ccw(self.pos, self.rot, self.current) {
-            Some((pos, rot)) => {
+    pub fn rot_ccw(&mut self) -> Option<B::Pos> {
+        match self.board.try_rot_ccw(self.pos) {
+            Some(pos) => {
                 self.pos = pos;
-                self.rot = rot;
 
-                Some((pos, rot))
+                Some(pos)
             }
             None => None,
         }
     }
 
-    pub fn drop_piece(&mut self) -> u8 {
-        while self.down().is_some() {}
+    pub fn outcome(&self) -> Outcome {
+        Outcome {
+            score: self.score,
+            lines: self.lines,
+            level: self.level,
+        }
+    }
 
-        self.lock();
+    /// Updates lines, level and score after clearing `lines_cleared` lines at once. Like the NES,
+    /// a clear that causes a level transition is scored on the new level.
+    pub fn add_lines(&mut self, lines_cleared: u8) {
+        self.lines += lines_cleared as usize;
+        self.level = self.start_level.after_lines(self.lines);
+        self.drop_speed = self.level.drop_speed();
+        self.score += self.level.line_clear_points(lines_cleared);
+    }
 
-        let mut lines_cleared = 0;
-        let mut r = 20;
+    /// Locks the current piece into the board and clears lines without spawning the next piece.
+    fn place(&mut self) -> u8 {
+        self.board.lock(self.pos);
 
-        while r < BOARD_SIZE {
-            if self.board.0[r..(r + BOARD_WIDTH)]
-                .iter()
-                .all(|p| p.is_some())
-            {
-                lines_cleared += 1;
-                for p in (20..r).rev() {
-                    self.board.0[p + BOARD_WIDTH] = self.board.0[p];
-                }
+        let lines_cleared = self.board.clear_lines();
+        self.add_lines(lines_cleared);
+
+        lines_cleared
+    }
+
+    /// Handles left and right with NES DAS: a new press moves immediately, after which the input
+    /// needs to be held for `DAS_CHARGE` frames before repeating every `DAS_REPEAT` frames. Running
+    /// into a wall or a block charges DAS fully.
+    fn shift(&mut self, held: Inputs, pressed: Inputs) {
+        if held.contains(Input::Down) {
+            return;
+        }
+
+        let dir = if held.contains(Input::Right) {
+            Input::Right
+        } else if held.contains(Input::Left) {
+            Input::Left
+        } else {
+            return;
+        };
+
+        if pressed.contains(Input::Left) || pressed.contains(Input::Right) {
+            self.das = 0;
+        } else {
+            self.das += 1;
+            if self.das < DAS_CHARGE {
+                return;
             }
-            r += 10;
+            self.das = DAS_CHARGE - DAS_REPEAT;
+        }
+
+        let moved = match dir {
+            Input::Right => self.right().is_some(),
+            _ => self.left().is_some(),
+        };
+
+        if !moved {
+            self.das = DAS_CHARGE;
+        }
+    }
+}
+
+impl<R: Rng, B: TetrisBoard> Game<R, B> {
+    pub fn drop_piece(&mut self) -> u8 {
+        while self.down().is_some() {}
+
+        self.lock()
+    }
+
+    /// Locks the current piece, clears lines and spawns the next piece. Returns the amount of
+    /// lines cleared. Does nothing once the game is finished.
+    pub fn lock(&mut self) -> u8 {
+        if self.state.is_finished() {
+            return 0;
         }
 
+        let lines_cleared = self.place();
+        self.spawn();
+
         lines_cleared
     }
 
-    pub fn lock(&mut self) {
-        for p in self.pos {
-            self.board.0[p as usize] = Some(self.current);
+    fn spawn(&mut self) {
+        self.pos = B::spawn_pos(self.next);
+        self.next = self.rng.next();
+
+        self.frames_since_drop = 0;
+        self.pushdown = 0;
+
+        if self.board.collides(self.pos) {
+            self.state = GameState::Finished(self.outcome());
+        }
+    }
+
+    /// Advances the game by exactly one NTSC frame with `inputs` held down. Like the NES, the
+    /// piece is first shifted (with DAS), then rotated and then pulled down by gravity, or every
+    /// `SOFT_DROP_SPEED` frames while soft dropping. When
+    /// gravity can't move the piece down it locks, and the next piece only spawns after the entry
+    /// delay and line clear animation have passed. Returns the amount of lines cleared on the
+    /// frame the piece locked. Does nothing once the game is finished.
+    pub fn step(&mut self, inputs: impl Into<Inputs>) -> Option<u8> {
+        if self.state.is_finished() {
+            return None;
         }
 
-        let mut r = 20;
+        let inputs = inputs.into();
+        let pressed = inputs.pressed_since(self.held);
+        self.held = inputs;
+        self.frame = self.frame.wrapping_add(1);
+        self.rng.frame();
 
-        while r < BOARD_SIZE {
-            if self.board.0[r..(r + BOARD_WIDTH)]
-                .iter()
-                .all(|p| p.is_some())
-            {
-                for p in (20..r).rev() {
-                    self.board.0[p + BOARD_WIDTH] = self.board.0[p];
-                }
+        if self.entry_delay > 0 {
+            self.entry_delay -= 1;
+            if self.entry_delay == 0 {
+                self.spawn();
             }
-            r += 10;
+            return None;
         }
 
-        self.pos = self.next.start_pos();
-        self.rot = Rotation::Right;
+        self.shift(inputs, pressed);
 
-        self.current = self.next;
-        self.next = self.rng.next();
+        if pressed.contains(Input::RotateCW) {
+            self.rot_cw();
+        } else if pressed.contains(Input::RotateCCW) {
+            self.rot_ccw();
+        }
+
+        let soft_drop = inputs.contains(Input::Down)
+            && !inputs.contains(Input::Left)
+            && !inputs.contains(Input::Right);
+        let speed = match soft_drop {
+            true => self.drop_speed.0.min(SOFT_DROP_SPEED),
+            false => self.drop_speed.0,
+        };
+
+        self.frames_since_drop += 1;
+        if self.frames_since_drop < speed {
+            return None;
+        }
+        self.frames_since_drop = 0;
+
+        if self.down().is_some() {
+            self.pushdown = if soft_drop { self.pushdown + 1 } else { 0 };
+            return None;
+        }
+
+        let Frames(mut delay) = entry_delay(B::placement(self.pos).lowest_row());
+
+        if soft_drop {
+            self.score += pushdown_points(self.pushdown);
+        }
+
+        let lines_cleared = self.place();
+        if lines_cleared > 0 {
+            delay += LINE_CLEAR_DELAY + (4 - (self.frame % 4) as u8) % 4;
+        }
+        self.entry_delay = delay;
+
+        Some(lines_cleared)
     }
 }
 
-impl<R> std::fmt::Display for Game<R> {
+impl<R> std::fmt::Display for Game<R, Board> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for i in 20..BOARD_SIZE {
             if i % BOARD_WIDTH == 0 {
                 writeln!(f)?
             }
 
-            if self.pos.contains(&(i as u8)) {
-                write!(f, "{} ", self.current)?;
+            if self.pos.cells.contains(&(i as u8)) {
+                write!(f, "{} ", self.current().to_string().to_lowercase())?;
                 continue;
             }
 
@@ -256,169 +568,295 @@ impl<R> std::fmt::Display for Game<R> {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RowGame<R> {
-    pub board: RowBoard,
-    pub next: Piece,
-    pub pos: PiecePos,
-    pub level: Level,
-    pub drop_speed: Frames,
-    pub score: usize,
-    pub rng: R,
-    // pub frames_since_last_move: u32,
-    // pub frames_since_drop: u8,
-    // pub finished: bool,
+impl<R> std::fmt::Display for Game<R, RowBoard> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cmp = 0b1000000000000000;
+        let masks = self.pos.get_masks();
+
+        let mut piece_board = [0u16; BOARD_HEIGHT];
+        for i in 0..4 {
+            piece_board[self.pos.y as usize + i] = masks[i];
+        }
+
+        let active = self.current().to_string().to_lowercase();
+
+        for (&r, mask) in self.board.0.iter().zip(piece_board).skip(3).take(20) {
+            let mut r = r << 3;
+            let mut mask = mask << 3;
+            for _ in 0..10 {
+                if mask & cmp == cmp {
+                    write!(f, "{active} ")?
+                } else if r & cmp == cmp {
+                    write!(f, "O ")?
+                } else {
+                    write!(f, ". ")?
+                }
+                r <<= 1;
+                mask <<= 1;
+            }
+            writeln!(f)?
+        }
+
+        Ok(())
+    }
 }
 
-impl<R: Rng> RowGame<R> {
-    pub fn new(level: impl Into<Level>) -> Self {
-        let mut rng = R::init();
-        let current = rng.next();
-        let next = rng.next();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_transition() {
+        assert_eq!(Level(0).first_transition(), 10);
+        assert_eq!(Level(5).first_transition(), 60);
+        assert_eq!(Level(9).first_transition(), 100);
+        assert_eq!(Level(12).first_transition(), 100);
+        assert_eq!(Level(15).first_transition(), 100);
+        assert_eq!(Level(18).first_transition(), 130);
+        assert_eq!(Level(19).first_transition(), 140);
+        assert_eq!(Level(29).first_transition(), 240);
+    }
 
-        let pos = current.row_start_pos();
+    #[test]
+    fn level_after_lines() {
+        assert_eq!(Level(18).after_lines(129), Level(18));
+        assert_eq!(Level(18).after_lines(130), Level(19));
+        assert_eq!(Level(18).after_lines(139), Level(19));
+        assert_eq!(Level(18).after_lines(230), Level(29));
+        assert_eq!(Level(0).after_lines(25), Level(2));
+    }
 
-        let board = RowBoard::new();
+    #[test]
+    fn line_clear_points() {
+        assert_eq!(Level(0).line_clear_points(0), 0);
+        assert_eq!(Level(0).line_clear_points(1), 40);
+        assert_eq!(Level(9).line_clear_points(2), 1000);
+        assert_eq!(Level(18).line_clear_points(3), 5700);
+        assert_eq!(Level(19).line_clear_points(4), 24000);
+    }
 
-        let level = level.into();
-        let drop_speed = level.drop_speed();
-        let score = 0;
+    #[test]
+    fn transition_tetris_scores_on_new_level() {
+        let mut game = Game::<OrderedRng>::new(18);
+        game.lines = 126;
 
-        Self {
-            board,
-            next,
-            pos,
-            level,
-            drop_speed,
-            score,
-            rng,
+        game.add_lines(4);
+
+        assert_eq!(game.lines, 130);
+        assert_eq!(game.level, Level(19));
+        assert_eq!(game.drop_speed, Frames(2));
+        assert_eq!(game.score, 24000);
+    }
+
+    #[test]
+    fn drop_piece_scores_cleared_lines() {
+        let mut game = Game::<OrderedRng>::new(0);
+
+        // fill the bottom two rows except for the columns of a vertical I on the far left
+        for x in 1..BOARD_WIDTH {
+            game.board.fill(x, 0, Piece::O);
+            game.board.fill(x, 1, Piece::O);
         }
+
+        assert_eq!(game.current(), Piece::I);
+        game.rot_cw();
+        while game.left().is_some() {}
+
+        assert_eq!(game.drop_piece(), 2);
+        assert_eq!(game.lines, 2);
+        assert_eq!(game.score, 100);
+        assert_eq!(game.board.0[BOARD_SIZE - BOARD_WIDTH], Some(Piece::I));
+        assert_eq!(game.board.0[BOARD_SIZE - 2 * BOARD_WIDTH], Some(Piece::I));
+        assert!(game.board.0[(BOARD_SIZE - BOARD_WIDTH + 1)..BOARD_SIZE]
+            .iter()
+            .all(|p| p.is_none()));
     }
 
-    pub fn from_board(board: RowBoard, level: impl Into<Level>) -> Self {
-        let mut res = Self::new(level);
-        res.board = board;
-        res
+    #[test]
+    fn entry_delay_by_height() {
+        assert_eq!(entry_delay(0), Frames(10));
+        assert_eq!(entry_delay(1), Frames(10));
+        assert_eq!(entry_delay(2), Frames(12));
+        assert_eq!(entry_delay(5), Frames(12));
+        assert_eq!(entry_delay(6), Frames(14));
+        assert_eq!(entry_delay(14), Frames(18));
+        assert_eq!(entry_delay(19), Frames(18));
     }
 
-    pub fn up(&mut self) -> Option<PiecePos> {
-        match self.board.try_up(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn gravity_follows_drop_speed() {
+        let mut game = Game::<OrderedRng>::new(19);
+        let start = game.pos;
 
-                Some(pos)
-            }
-            None => None,
-        }
+        game.step(Inputs::NONE);
+        assert_eq!(game.pos, start);
+
+        game.step(Inputs::NONE);
+        assert_eq!(game.pos.cells, start.cells.map(|p| p + BW));
     }
 
-    pub fn down(&mut self) -> Option<PiecePos> {
-        match self.board.try_down(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn das_timing() {
+        let mut game = Game::<OrderedRng>::new(0);
+        let mut moves = vec![];
 
-                Some(pos)
+        for frame in 1..=30 {
+            let before = game.pos;
+            game.step(Input::Right);
+            if game.pos.cells[0] % BW != before.cells[0] % BW {
+                moves.push(frame);
             }
-            None => None,
         }
+
+        // the I spawns 3 columns from the wall, so the last shift is blocked
+        assert_eq!(moves, vec![1, 17, 23]);
+        assert_eq!(game.das, DAS_CHARGE);
     }
 
-    pub fn left(&mut self) -> Option<PiecePos> {
-        match self.board.try_left(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn rotation_needs_a_new_press() {
+        let mut game = Game::<OrderedRng>::new(0);
 
-                Some(pos)
-            }
-            None => None,
-        }
+        game.step(Input::RotateCW);
+        let rotated = game.rot();
+        game.step(Input::RotateCW);
+
+        assert_eq!(rotated, Rotation::Down);
+        assert_eq!(game.rot(), Rotation::Down);
     }
 
-    pub fn right(&mut self) -> Option<PiecePos> {
-        match self.board.try_right(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn soft_drop_every_other_frame() {
+        let mut game = Game::<OrderedRng>::new(0);
+        let start = game.pos;
 
-                Some(pos)
-            }
-            None => None,
+        for _ in 0..10 {
+            game.step(Input::Down);
         }
+
+        assert_eq!(game.pos.cells, start.cells.map(|p| p + 5 * BW));
+        assert_eq!(game.pushdown, 5);
     }
 
-    pub fn rot_cw(&mut self) -> Option<PiecePos> {
-        match self.board.try_rot_cw(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn soft_drop_stops_shifting() {
+        let mut game = Game::<OrderedRng>::new(0);
+        let start = game.pos;
 
-                Some(pos)
-            }
-            None => None,
+        for _ in 0..10 {
+            game.step([Input::Down, Input::Right]);
         }
+
+        assert_eq!(game.pos, start);
     }
 
-    pub fn rot_ccw(&mut self) -> Option<PiecePos> {
-        match self.board.try_rot_ccw(self.pos) {
-            Some(pos) => {
-                self.pos = pos;
+    #[test]
+    fn pushdown_scores_points() {
+        let mut game = Game::<OrderedRng>::new(0);
 
-                Some(pos)
-            }
-            None => None,
-        }
+        let lines = (0..100).find_map(|_| game.step(Input::Down));
+
+        // the I falls from the third row to the bottom
+        assert_eq!(lines, Some(0));
+        assert_eq!(game.score, 18);
+        assert_eq!(pushdown_points(1), 0);
     }
 
-    fn lock(&mut self) {
-        let masks = self.pos.get_masks();
-        masks.into_iter().enumerate().for_each(|(i, m)|
-            self.board.0[self.pos.y as usize + i] |= m
-        );
-
-        for r in 3..=(MAX_Y as usize) {
-            if self.board.0[r] == FULL_LINE {
-                for i in 0..r {
-                    self.board.0[r - i] = self.board.0[r - i - 1]
-                }
-            }
+    #[test]
+    fn entry_delay_after_lock() {
+        let mut game = Game::<OrderedRng>::new(29);
+
+        let lines = (0..30).find_map(|_| game.step(Inputs::NONE));
+        assert_eq!(lines, Some(0));
+        assert_eq!(game.entry_delay, 10);
+
+        for _ in 0..9 {
+            game.step(Inputs::NONE);
+            assert_eq!(game.current(), Piece::I);
         }
 
-        self.pos = self.next.row_start_pos();
-        self.next = self.rng.next();
+        game.step(Inputs::NONE);
+        assert_eq!(game.current(), Piece::L);
+        assert_eq!(game.pos.cells, Piece::L.start_pos());
     }
 
-    pub fn drop_piece(&mut self) {
-        while self.down().is_some() {}
+    #[test]
+    fn top_out_when_spawn_overlaps() {
+        let mut game = Game::<OrderedRng>::new(0);
+        game.rot_cw();
+        while game.left().is_some() {}
+
+        for (x, y) in Board::placement(Board::spawn_pos(Piece::L)).cells {
+            game.board.fill(x.into(), y.into(), Piece::O);
+        }
+
+        assert_eq!(game.state, GameState::Playing);
+        game.drop_piece();
 
-        self.lock();
+        let outcome = Outcome {
+            score: 0,
+            lines: 0,
+            level: Level(0),
+        };
+        assert_eq!(game.state, GameState::Finished(outcome));
+
+        let board = game.board.clone();
+        assert_eq!(game.drop_piece(), 0);
+        assert_eq!(game.step(Inputs::NONE), None);
+        assert_eq!(game.board.0, board.0);
     }
-}
 
-impl<R> std::fmt::Display for RowGame<R> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let cmp = 0b1000000000000000;
-        let masks = self
-            .pos
-            .get_masks();
+    #[test]
+    fn row_game_top_out() {
+        let mut game = RowGame::<OrderedRng>::new(0);
+        game.rot_cw();
+        while game.left().is_some() {}
 
-        let mut piece_board = [0u16; BOARD_HEIGHT];
-        for i in 0..4 {
-            piece_board[self.pos.y as usize + i] = masks[i];
+        for (x, y) in RowBoard::placement(RowBoard::spawn_pos(Piece::L)).cells {
+            game.board.fill(x.into(), y.into(), Piece::O);
         }
 
-        for (&r, mask) in self.board.0.iter().zip(piece_board).skip(3).take(20) {
-            let mut r = r;
-            r |= mask;
-            r <<= 3;
-            for _ in 0..10 {
-                if r & cmp == cmp {
-                    write!(f, "O ")?
-                } else {
-                    write!(f, ". ")?
-                }
-                r <<= 1;
-            }
-            writeln!(f)?
+        game.drop_piece();
+
+        assert!(game.state.is_finished());
+    }
+
+    #[test]
+    fn seeded_games_are_reproducible() {
+        let mut a = Game::<SevenBag>::from_seed(1234, 18);
+        let mut b = Game::<SevenBag>::from_seed(1234, 18);
+
+        for _ in 0..50 {
+            assert_eq!((a.current(), a.next), (b.current(), b.next));
+            a.drop_piece();
+            b.drop_piece();
         }
+    }
 
-        Ok(())
+    #[test]
+    fn from_board_refreshes_features() {
+        let mut board = Board::new();
+        board.0[BOARD_SIZE - 1] = Some(Piece::O);
+
+        let game = Game::from_board(board, OrderedRng::from_seed(0), 0);
+
+        assert_eq!(game.board.1, BoardFeatures::new(&game.board));
+        assert_eq!(game.board.1.heights[9], 1);
+    }
+
+    #[test]
+    fn games_from_a_diagram_are_reproducible() {
+        let diagram = "
+            . . . . . . . . . .
+            O O O O . . . O O O
+        ";
+        let mut a = Game::<_, Board>::from_diagram(diagram, SevenBag::from_seed(99), 18).unwrap();
+        let mut b = Game::<_, Board>::from_diagram(diagram, SevenBag::from_seed(99), 18).unwrap();
+
+        for _ in 0..8 {
+            assert_eq!((a.current(), a.next), (b.current(), b.next));
+            a.drop_piece();
+            b.drop_piece();
+        }
     }
 }
@@ -121,6 +121,20 @@ impl Piece {
     pub const START_POS_S: PiecePositions = [25, 26, 34, 35];
     pub const START_POS_Z: PiecePositions = [24, 25, 35, 36];
 
+    /// Inverse of the `Display` impl, which prints the uppercase letter of the piece.
+    pub const fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'I' => Some(Self::I),
+            'L' => Some(Self::L),
+            'J' => Some(Self::J),
+            'O' => Some(Self::O),
+            'T' => Some(Self::T),
+            'S' => Some(Self::S),
+            'Z' => Some(Self::Z),
+            _ => None,
+        }
+    }
+
     pub fn random() -> Self {
         let mut rng = WyRand::new();
 
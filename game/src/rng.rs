@@ -2,25 +2,54 @@ use nanorand::{Rng as _, WyRand};
 
 use crate::pieces::Piece;
 
-pub trait Rng {
-    fn init() -> Self;
+/// A fresh seed from system entropy, for when a game doesn't need to be reproduced. Log it to be
+/// able to replay the same piece sequence later.
+pub fn random_seed() -> u64 {
+    WyRand::new().generate()
+}
+
+pub trait Rng: Clone {
+    /// Creates a randomizer seeded from system entropy.
+    fn init() -> Self {
+        Self::from_seed(random_seed())
+    }
+
+    /// Creates a randomizer that always produces the same sequence for the same seed.
+    fn from_seed(seed: u64) -> Self;
 
     fn next(&mut self) -> Piece;
+
+    /// Probability of each piece being drawn by the next call to `next`, given the current state,
+    /// indexed in the order of `Piece::PIECES`.
+    fn probabilities(&self) -> [f32; 7];
+
+    /// Called once for every frame the game advances. Randomizers whose state depends on timing
+    /// can advance here.
+    fn frame(&mut self) {}
+
+    /// Captures the current state, so the same pieces can be drawn again after `restore`.
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    fn restore(&mut self, snapshot: Self) {
+        *self = snapshot
+    }
 }
 
 /// Not actually classic... But close enough
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ClassicRng {
     rng: WyRand,
     current: Piece,
 }
 
 impl Rng for ClassicRng {
-    fn init() -> Self {
-        Self {
-            rng: WyRand::new(),
-            current: Piece::random(),
-        }
+    fn from_seed(seed: u64) -> Self {
+        let mut rng = WyRand::new_seed(seed);
+        let current = Piece::PIECES[rng.generate_range(0..7)];
+
+        Self { rng, current }
     }
 
     fn next(&mut self) -> Piece {
@@ -50,9 +79,18 @@ impl Rng for ClassicRng {
 
         res
     }
+
+    /// Every piece but the previous one gets a direct 1 in 8 roll, and the previous piece or the
+    /// eighth slot reroll uniformly over all seven.
+    fn probabilities(&self) -> [f32; 7] {
+        let reroll = 2.0 / 8.0 / 7.0;
+        let mut res = [1.0 / 8.0 + reroll; 7];
+        res[self.current as usize] = reroll;
+        res
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SevenBag {
     rng: WyRand,
     current: usize,
@@ -66,15 +104,16 @@ impl SevenBag {
 }
 
 impl Rng for SevenBag {
-    fn init() -> Self {
+    fn from_seed(seed: u64) -> Self {
         let current = 0;
-        let mut rng = WyRand::new();
+        let mut rng = WyRand::new_seed(seed);
         let mut bag = Piece::PIECES;
         rng.shuffle(&mut bag);
 
         Self { current, rng, bag }
     }
 
+    #[allow(clippy::manual_is_multiple_of)]
     fn next(&mut self) -> Piece {
         let res = self.bag[self.current];
         self.current += 1;
@@ -86,18 +125,33 @@ impl Rng for SevenBag {
 
         res
     }
+
+    /// Uniform over the pieces left in the bag.
+    fn probabilities(&self) -> [f32; 7] {
+        let left = &self.bag[self.current..];
+        let mut res = [0.0; 7];
+        left.iter()
+            .for_each(|&p| res[p as usize] = 1.0 / left.len() as f32);
+        res
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OrderedRng {
     index: usize,
     bag: [Piece; 7],
 }
 
 impl Rng for OrderedRng {
+    /// Always starts with `Piece::I`.
     fn init() -> Self {
+        Self::from_seed(0)
+    }
+
+    /// The seed picks which piece the cycle starts on.
+    fn from_seed(seed: u64) -> Self {
         Self {
-            index: 0,
+            index: (seed % 7) as usize,
             bag: Piece::PIECES,
         }
     }
@@ -107,4 +161,403 @@ impl Rng for OrderedRng {
         self.index = (self.index + 1) % 7;
         piece
     }
+
+    fn probabilities(&self) -> [f32; 7] {
+        certain(self.bag[self.index])
+    }
+}
+
+/// Distribution where only `piece` can come next.
+fn certain(piece: Piece) -> [f32; 7] {
+    let mut res = [0.0; 7];
+    res[piece as usize] = 1.0;
+    res
+}
+
+/// Yields a fixed list of pieces, starting over once it runs out.
+#[derive(Debug, Clone)]
+pub struct SequenceRng {
+    pieces: Vec<Piece>,
+    index: usize,
+}
+
+impl SequenceRng {
+    /// Panics when `pieces` is empty.
+    pub fn new(pieces: impl Into<Vec<Piece>>) -> Self {
+        let pieces = pieces.into();
+        assert!(
+            !pieces.is_empty(),
+            "a piece sequence needs at least one piece"
+        );
+
+        Self { pieces, index: 0 }
+    }
+}
+
+impl Rng for SequenceRng {
+    /// Cycles through all seven pieces, starting at the piece picked by the seed.
+    fn from_seed(seed: u64) -> Self {
+        let mut res = Self::new(Piece::PIECES);
+        res.index = (seed % 7) as usize;
+        res
+    }
+
+    fn next(&mut self) -> Piece {
+        let piece = self.pieces[self.index];
+        self.index = (self.index + 1) % self.pieces.len();
+        piece
+    }
+
+    fn probabilities(&self) -> [f32; 7] {
+        certain(self.pieces[self.index])
+    }
+}
+
+/// Yields a fixed list of pieces once and then continues with another randomizer.
+#[derive(Debug, Clone)]
+pub struct ScriptedRng<R> {
+    pieces: Vec<Piece>,
+    index: usize,
+    then: R,
+}
+
+impl<R> ScriptedRng<R> {
+    pub fn new(pieces: impl Into<Vec<Piece>>, then: R) -> Self {
+        Self {
+            pieces: pieces.into(),
+            index: 0,
+            then,
+        }
+    }
+
+    /// Pieces of the script that haven't been drawn yet.
+    pub fn remaining(&self) -> &[Piece] {
+        &self.pieces[self.index..]
+    }
+}
+
+impl<R: Rng> Rng for ScriptedRng<R> {
+    /// An empty script, so every piece comes from `R` seeded with `seed`.
+    fn from_seed(seed: u64) -> Self {
+        Self::new([], R::from_seed(seed))
+    }
+
+    fn next(&mut self) -> Piece {
+        match self.pieces.get(self.index) {
+            Some(&piece) => {
+                self.index += 1;
+                piece
+            }
+            None => self.then.next(),
+        }
+    }
+
+    fn probabilities(&self) -> [f32; 7] {
+        match self.pieces.get(self.index) {
+            Some(&piece) => certain(piece),
+            None => self.then.probabilities(),
+        }
+    }
+
+    fn frame(&mut self) {
+        self.then.frame()
+    }
+}
+
+/// The randomizer of the NES cartridge. A 16 bit LFSR is stepped once per frame, and on every spawn
+/// the high byte plus a spawn counter picks one of 8 slots. When that lands on the unused eighth
+/// slot or on the previous piece, the LFSR is stepped once more and the piece is picked again from
+/// its low 3 bits plus the previous piece's orientation id, which can still repeat the previous
+/// piece.
+#[derive(Debug, Clone)]
+pub struct NesRng {
+    seed: u16,
+    spawn_count: u8,
+    spawn_id: u8,
+}
+
+impl NesRng {
+    /// LFSR value the cartridge starts with at power on.
+    pub const POWER_ON_SEED: u16 = 0x8988;
+
+    /// Pieces in the order of the cartridge's spawn table, with the orientation id it stores for
+    /// each of them.
+    const SPAWN_TABLE: [(Piece, u8); 7] = [
+        (Piece::T, 0x02),
+        (Piece::J, 0x07),
+        (Piece::Z, 0x08),
+        (Piece::O, 0x0A),
+        (Piece::S, 0x0B),
+        (Piece::L, 0x0E),
+        (Piece::I, 0x12),
+    ];
+
+    /// Starts the randomizer from the given LFSR value. A seed of 0 never changes, so it results
+    /// in a very boring sequence.
+    pub const fn new(seed: u16) -> Self {
+        Self {
+            seed,
+            spawn_count: 0,
+            spawn_id: 0,
+        }
+    }
+
+    pub const fn seed(&self) -> u16 {
+        self.seed
+    }
+
+    /// Steps the LFSR once. The new high bit is bit 1 xor bit 9 of the old value.
+    pub fn step(&mut self) {
+        let bit = ((self.seed >> 9) ^ (self.seed >> 1)) & 1;
+        self.seed = (bit << 15) | (self.seed >> 1);
+    }
+
+    const fn high(&self) -> u8 {
+        (self.seed >> 8) as u8
+    }
+}
+
+impl Rng for NesRng {
+    /// Uses the low 16 bits of the seed as the LFSR value, or `POWER_ON_SEED` when those are 0.
+    fn from_seed(seed: u64) -> Self {
+        match seed as u16 {
+            0 => Self::new(Self::POWER_ON_SEED),
+            seed => Self::new(seed),
+        }
+    }
+
+    fn next(&mut self) -> Piece {
+        self.spawn_count = self.spawn_count.wrapping_add(1);
+
+        let mut index = (self.high().wrapping_add(self.spawn_count) & 7) as usize;
+
+        if index == 7 || Self::SPAWN_TABLE[index].1 == self.spawn_id {
+            self.step();
+            index = ((self.high() & 7) as usize + self.spawn_id as usize) % 7;
+        }
+
+        let (piece, spawn_id) = Self::SPAWN_TABLE[index];
+        self.spawn_id = spawn_id;
+
+        piece
+    }
+
+    /// The piece is fully determined by the LFSR, but since it's stepped every frame and the time
+    /// until the next spawn isn't known, this treats its bits as uniform. That leaves the previous
+    /// piece as the only state that matters: it is only reachable through the reroll, which favors
+    /// one slot over the others because 8 values are folded onto 7 pieces.
+    fn probabilities(&self) -> [f32; 7] {
+        let prev = Self::SPAWN_TABLE
+            .iter()
+            .position(|&(_, id)| id == self.spawn_id);
+        let reroll = if prev.is_some() { 2.0 / 8.0 } else { 1.0 / 8.0 };
+
+        let mut res = [0.0; 7];
+
+        for (j, &(piece, _)) in Self::SPAWN_TABLE.iter().enumerate() {
+            let rerolled_to = (0..8)
+                .filter(|k| (k + self.spawn_id as usize) % 7 == j)
+                .count();
+
+            let direct = if prev == Some(j) { 0.0 } else { 1.0 / 8.0 };
+            res[piece as usize] = direct + reroll * rerolled_to as f32 / 8.0;
+        }
+
+        res
+    }
+
+    fn frame(&mut self) {
+        self.step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nes_lfsr_period() {
+        let mut rng = NesRng::new(NesRng::POWER_ON_SEED);
+        let mut period = 0;
+
+        loop {
+            rng.step();
+            period += 1;
+
+            if rng.seed() == NesRng::POWER_ON_SEED {
+                break;
+            }
+        }
+
+        assert_eq!(period, 32767);
+    }
+
+    #[test]
+    fn nes_sequence_is_deterministic() {
+        let mut a = NesRng::new(0x1234);
+        let mut b = NesRng::new(0x1234);
+
+        for frames in 0..500 {
+            for _ in 0..frames % 13 {
+                a.frame();
+                b.frame();
+            }
+            assert_eq!(a.next(), b.next());
+        }
+    }
+
+    #[test]
+    fn nes_rerolls_repeats() {
+        let mut rng = NesRng::new(NesRng::POWER_ON_SEED);
+        let mut counts = [0usize; 7];
+        let mut repeats = 0;
+        let mut prev = rng.next();
+
+        for i in 0..7000 {
+            for _ in 0..(i % 37 + 20) {
+                rng.frame();
+            }
+
+            let piece = rng.next();
+            counts[piece as usize] += 1;
+            if piece == prev {
+                repeats += 1;
+            }
+            prev = piece;
+        }
+
+        assert!(counts.iter().all(|&c| c > 700), "{counts:?}");
+        // an unbiased randomizer would repeat 1 in 7 times, the reroll brings that down to ~1 in 32
+        assert!(repeats < 400, "{repeats} repeats");
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        fn check<R: Rng>() {
+            let mut a = R::from_seed(42);
+            let mut b = R::from_seed(42);
+
+            for _ in 0..100 {
+                assert_eq!(a.next(), b.next());
+            }
+        }
+
+        check::<ClassicRng>();
+        check::<SevenBag>();
+        check::<OrderedRng>();
+        check::<NesRng>();
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut rng = SevenBag::from_seed(7);
+        rng.next();
+
+        let snapshot = rng.snapshot();
+        let first = (0..20).map(|_| rng.next()).collect::<Vec<_>>();
+
+        rng.restore(snapshot);
+        let second = (0..20).map(|_| rng.next()).collect::<Vec<_>>();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn sequence_loops() {
+        use Piece::*;
+
+        let mut rng = SequenceRng::new([S, Z, I]);
+        let drawn = (0..7).map(|_| rng.next()).collect::<Vec<_>>();
+
+        assert_eq!(drawn, [S, Z, I, S, Z, I, S]);
+    }
+
+    #[test]
+    fn script_falls_back() {
+        use Piece::*;
+
+        let mut rng = ScriptedRng::new([T, T], OrderedRng::init());
+        let drawn = (0..5).map(|_| rng.next()).collect::<Vec<_>>();
+
+        assert_eq!(drawn, [T, T, I, L, J]);
+        assert!(rng.remaining().is_empty());
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        fn check<R: Rng>(mut rng: R) {
+            for _ in 0..20 {
+                let sum = rng.probabilities().iter().sum::<f32>();
+                assert!((sum - 1.0).abs() < 1e-5, "{sum}");
+                rng.next();
+            }
+        }
+
+        check(ClassicRng::from_seed(1));
+        check(SevenBag::from_seed(1));
+        check(OrderedRng::init());
+        check(NesRng::from_seed(1));
+        check(SequenceRng::new([Piece::S, Piece::Z]));
+        check(ScriptedRng::new([Piece::T], NesRng::from_seed(1)));
+    }
+
+    #[test]
+    fn classic_probabilities() {
+        let mut rng = ClassicRng::from_seed(3);
+        let prev = rng.next();
+        let probabilities = rng.probabilities();
+
+        for piece in Piece::PIECES {
+            let expected = if piece == prev {
+                2.0 / 56.0
+            } else {
+                9.0 / 56.0
+            };
+            assert!((probabilities[piece as usize] - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn bag_probabilities() {
+        let mut rng = SevenBag::from_seed(3);
+        let drawn = (0..5).map(|_| rng.next()).collect::<Vec<_>>();
+        let probabilities = rng.probabilities();
+
+        for piece in Piece::PIECES {
+            let expected = if drawn.contains(&piece) { 0.0 } else { 0.5 };
+            assert_eq!(probabilities[piece as usize], expected);
+        }
+    }
+
+    #[test]
+    fn nes_probabilities_match_draws() {
+        let mut rng = NesRng::new(NesRng::POWER_ON_SEED);
+        rng.next();
+
+        let mut counts = [[0usize; 7]; 7];
+        let mut expected = [[0.0f32; 7]; 7];
+
+        for i in 0..70_000 {
+            for _ in 0..(i % 29 + 30) {
+                rng.frame();
+            }
+
+            let prev = NesRng::SPAWN_TABLE
+                .iter()
+                .position(|&(_, id)| id == rng.spawn_id)
+                .unwrap();
+            expected[prev] = rng.probabilities();
+            counts[prev][rng.next() as usize] += 1;
+        }
+
+        for (counts, expected) in counts.iter().zip(expected) {
+            let total = counts.iter().sum::<usize>() as f32;
+            for (&c, e) in counts.iter().zip(expected) {
+                assert!(
+                    (c as f32 / total - e).abs() < 0.02,
+                    "{counts:?} {expected:?}"
+                );
+            }
+        }
+    }
 }
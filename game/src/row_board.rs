@@ -1,4 +1,4 @@
-use crate::{consts_row::*, pieces::*};
+use crate::{board::*, consts_row::*, pieces::*, tetris_board::*};
 
 pub const BOARD_HEIGHT: usize = 26;
 pub const BOARD_HEIGHT_U8: u8 = BOARD_HEIGHT as u8;
@@ -65,17 +65,46 @@ impl PiecePos {
     }
 }
 
+/// Rows removed by `RowBoard::clear_lines`, as a bit per row index from before the clear.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ClearedLines(pub u32);
+
+impl ClearedLines {
+    pub const fn count(&self) -> u8 {
+        self.0.count_ones() as u8
+    }
+
+    /// Indices of the cleared rows, from the top down.
+    pub fn rows(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+
+        (0..BOARD_HEIGHT).filter(move |&r| bits & (1 << r) != 0)
+    }
+}
+
+/// What `RowBoard::place` changed, so `RowBoard::undo` can put the board back like it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct Undo {
+    pub pos: PiecePos,
+    pub cleared: ClearedLines,
+    /// Features of the board before the piece was placed.
+    pub features: BoardFeatures,
+}
+
+/// Rows of the board, and its features. Changing the rows directly leaves the features out of
+/// date, so prefer `TetrisBoard::lock`, `TetrisBoard::unlock` and `TetrisBoard::fill`.
 #[derive(Clone, Debug)]
-pub struct RowBoard(pub [u16; BOARD_HEIGHT]);
+pub struct RowBoard(pub [u16; BOARD_HEIGHT], pub BoardFeatures);
 
 impl Default for RowBoard {
     fn default() -> Self {
         let board = [
-            0, 0, 0, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
             BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
-            FULL_LINE, FULL_LINE, FULL_LINE,
+            BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
+            BOUNDS, FULL_LINE, FULL_LINE, FULL_LINE,
         ];
-        Self(board)
+        Self(board, BoardFeatures::EMPTY)
     }
 }
 
@@ -83,11 +112,11 @@ impl RowBoard {
     #[inline]
     pub const fn new() -> Self {
         let board = [
-            0, 0, 0, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
             BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
-            FULL_LINE, FULL_LINE, FULL_LINE,
+            BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS, BOUNDS,
+            BOUNDS, FULL_LINE, FULL_LINE, FULL_LINE,
         ];
-        Self(board)
+        Self(board, BoardFeatures::EMPTY)
     }
 
     #[inline]
@@ -193,6 +222,73 @@ impl RowBoard {
         }
     }
 
+    /// Removes all full visible rows, moving everything above them down and keeping the walls
+    /// intact. Rows are only moved, so the hidden rows move down into the visible ones.
+    pub fn clear_lines(&mut self) -> ClearedLines {
+        let mut cleared = ClearedLines::default();
+        let mut write = MAX_Y as usize + 1;
+
+        for read in (0..=MAX_Y as usize).rev() {
+            if read >= 3 && self.0[read] == FULL_LINE {
+                cleared.0 |= 1 << read;
+                continue;
+            }
+
+            write -= 1;
+            self.0[write] = self.0[read];
+        }
+
+        self.0[..write].fill(BOUNDS);
+        let mut features = self.1;
+        features.clear(
+            self,
+            cleared
+                .rows()
+                .fold(0, |rows, r| rows | 1 << (MAX_Y as usize - r)),
+        );
+        self.1 = features;
+
+        cleared
+    }
+
+    /// Locks the piece at `pos` and clears lines, like `RowGame::lock` does to its board.
+    pub fn place(&mut self, pos: PiecePos) -> Undo {
+        let features = self.1;
+
+        TetrisBoard::lock(self, pos);
+        let cleared = self.clear_lines();
+
+        Undo {
+            pos,
+            cleared,
+            features,
+        }
+    }
+
+    /// Takes back a `place`, which has to be the last change made to the board. The board ends up
+    /// exactly like it was before.
+    pub fn undo(&mut self, undo: Undo) {
+        // The rows that were kept are packed at the bottom, so spreading them out again from the
+        // top down never overwrites one before it is read.
+        let mut read = undo.cleared.count() as usize;
+
+        for write in 0..=MAX_Y as usize {
+            self.0[write] = match undo.cleared.0 & (1 << write) != 0 {
+                true => FULL_LINE,
+                false => {
+                    read += 1;
+                    self.0[read - 1]
+                }
+            };
+        }
+
+        for (i, m) in undo.pos.get_masks().into_iter().enumerate() {
+            self.0[undo.pos.y as usize + i] &= !m
+        }
+
+        self.1 = undo.features;
+    }
+
     #[inline]
     pub fn find_highest_blocks(&self) -> [u8; 10] {
         let mut res = [BOARD_HEIGHT_U8; 10];
@@ -213,10 +309,213 @@ impl RowBoard {
     }
 }
 
+impl TetrisBoard for RowBoard {
+    type Pos = PiecePos;
+
+    fn spawn_pos(piece: Piece) -> PiecePos {
+        piece.row_start_pos()
+    }
+
+    fn piece(pos: PiecePos) -> Piece {
+        pos.piece
+    }
+
+    fn rotation(pos: PiecePos) -> Rotation {
+        pos.rot
+    }
+
+    fn placement(pos: PiecePos) -> Placement {
+        let mut cells = [(0, 0); 4];
+        let mut i = 0;
+
+        for (row, mask) in pos.get_masks().into_iter().enumerate() {
+            for x in 0..FIELD_WIDTH as u8 {
+                if mask & (1 << (12 - x)) != 0 {
+                    cells[i] = (x, MAX_Y - pos.y - row as u8);
+                    i += 1;
+                }
+            }
+        }
+
+        Placement::new(pos.piece, pos.rot, cells)
+    }
+
+    fn from_placement(placement: Placement) -> PiecePos {
+        let spawn = PiecePos {
+            rot: placement.rot,
+            ..placement.piece.row_start_pos()
+        };
+        let (x0, y0) = Self::placement(spawn).cells[0];
+        let (x1, y1) = placement.cells[0];
+
+        let x = spawn.x as i16 + x1 as i16 - x0 as i16;
+        let y = spawn.y as i16 + y0 as i16 - y1 as i16;
+        assert!(
+            (0..=MAX_Y as i16).contains(&y),
+            "placement is above the board"
+        );
+
+        PiecePos {
+            x: x as u8,
+            y: y as u8,
+            ..spawn
+        }
+    }
+
+    #[inline]
+    fn state_index(pos: PiecePos) -> usize {
+        pos.y as usize * 16 + pos.x as usize
+    }
+
+    #[inline]
+    fn collides(&self, pos: PiecePos) -> bool {
+        !self.no_collision(pos)
+    }
+
+    #[inline]
+    fn try_up(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_up(self, pos)
+    }
+
+    #[inline]
+    fn try_down(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_down(self, pos)
+    }
+
+    #[inline]
+    fn try_left(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_left(self, pos)
+    }
+
+    #[inline]
+    fn try_right(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_right(self, pos)
+    }
+
+    #[inline]
+    fn try_rot_cw(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_rot_cw(self, pos)
+    }
+
+    #[inline]
+    fn try_rot_ccw(&self, pos: PiecePos) -> Option<PiecePos> {
+        RowBoard::try_rot_ccw(self, pos)
+    }
+
+    fn lock(&mut self, pos: PiecePos) {
+        for (i, m) in pos.get_masks().into_iter().enumerate() {
+            self.0[pos.y as usize + i] |= m
+        }
+
+        for cell in Self::placement(pos).cells {
+            self.1.add(cell);
+        }
+    }
+
+    fn unlock(&mut self, pos: PiecePos) {
+        for (i, m) in pos.get_masks().into_iter().enumerate() {
+            self.0[pos.y as usize + i] &= !m
+        }
+
+        let mut features = self.1;
+        for cell in Self::placement(pos).cells {
+            features.remove(self, cell);
+        }
+        self.1 = features;
+    }
+
+    fn clear_lines(&mut self) -> u8 {
+        RowBoard::clear_lines(self).count()
+    }
+
+    #[inline]
+    fn filled(&self, x: usize, y: usize) -> bool {
+        y <= MAX_Y as usize && self.0[MAX_Y as usize - y] & (1 << (12 - x)) != 0
+    }
+
+    fn fill(&mut self, x: usize, y: usize, _: Piece) {
+        if self.filled(x, y) {
+            return;
+        }
+
+        self.0[MAX_Y as usize - y] |= 1 << (12 - x);
+        self.1.add((x as u8, y as u8));
+    }
+
+    fn features(&self) -> &BoardFeatures {
+        &self.1
+    }
+
+    fn refresh_features(&mut self) {
+        self.1 = BoardFeatures::new(self);
+    }
+}
+
+/// Reads a diagram like the one `Display` prints, see `parse_diagram`. An active piece in the
+/// diagram is left out.
+impl std::str::FromStr for RowBoard {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_diagram(s).map(|(board, _)| board)
+    }
+}
+
+/// Copies the blocks over. Which piece a block came from is lost.
+impl From<&Board> for RowBoard {
+    fn from(board: &Board) -> Self {
+        let mut res = RowBoard::new();
+
+        for y in 0..BOARD_SIZE / BOARD_WIDTH {
+            for x in 0..FIELD_WIDTH {
+                if board.filled(x, y) {
+                    res.fill(x, y, Piece::O);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+/// Copies the blocks over as `Piece::O`. The topmost hidden row of a `RowBoard` doesn't exist on a
+/// `Board`, so blocks in it are dropped.
+impl From<&RowBoard> for Board {
+    fn from(board: &RowBoard) -> Self {
+        let mut res = Board::new();
+        let rows = BOARD_SIZE / BOARD_WIDTH;
+
+        for y in 0..rows {
+            for x in 0..FIELD_WIDTH {
+                if board.filled(x, y) {
+                    res.fill(x, y, Piece::O);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl From<BoardPos> for PiecePos {
+    fn from(pos: BoardPos) -> Self {
+        RowBoard::from_placement(Board::placement(pos))
+    }
+}
+
+impl From<PiecePos> for BoardPos {
+    /// # Panics
+    ///
+    /// If the piece is partly in the topmost hidden row of the `RowBoard`.
+    fn from(pos: PiecePos) -> Self {
+        Board::from_placement(RowBoard::placement(pos))
+    }
+}
+
 impl std::fmt::Display for RowBoard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let cmp = 0b1000000000000000;
-        for &r in &self.0[2..] {
+        for &r in &self.0[3..=MAX_Y as usize] {
             let mut r = r;
             r <<= 3;
             for _ in 0..10 {
@@ -233,3 +532,177 @@ impl std::fmt::Display for RowBoard {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_round_trip() {
+        let mut board = Board::new();
+        board.lock([0, 1, 10, 11], Piece::O);
+        board.lock([200, 201, 202, 203], Piece::I);
+        board.lock([175, 185, 195, 205], Piece::I);
+        board.lock([209, 219, 218, 217], Piece::J);
+
+        let row_board = RowBoard::from(&board);
+        let back = Board::from(&row_board);
+
+        for y in 0..BOARD_SIZE / BOARD_WIDTH {
+            for x in 0..FIELD_WIDTH {
+                assert_eq!(board.filled(x, y), row_board.filled(x, y), "({x}, {y})");
+                assert_eq!(board.filled(x, y), back.filled(x, y), "({x}, {y})");
+            }
+        }
+
+        assert!(row_board.0[..=MAX_Y as usize]
+            .iter()
+            .all(|r| r & BOUNDS == BOUNDS));
+        assert_eq!(row_board.0[MAX_Y as usize + 1..], [FULL_LINE; 3]);
+    }
+
+    fn assert_walls(board: &RowBoard) {
+        assert!(board.0[..=MAX_Y as usize]
+            .iter()
+            .all(|r| r & BOUNDS == BOUNDS));
+        assert_eq!(board.0[MAX_Y as usize + 1..], [FULL_LINE; 3]);
+    }
+
+    #[test]
+    fn clear_single() {
+        let mut board = crate::row_board!(
+            "
+            ....O.....
+            OOOOOOOOOO
+            O.OOOOOOOO
+            "
+        );
+
+        let cleared = board.clear_lines();
+
+        assert_eq!(cleared.count(), 1);
+        assert_eq!(cleared.rows().collect::<Vec<_>>(), [21]);
+        assert_eq!(board.0, crate::row_board!("....O.....\nO.OOOOOOOO").0);
+        assert_walls(&board);
+    }
+
+    #[test]
+    fn clear_split() {
+        let mut board = crate::row_board!(
+            "
+            ...O......
+            OOOOOOOOOO
+            OOOOOOOOO.
+            OOOOOOOOOO
+            .OOOOOOOOO
+            "
+        );
+
+        let cleared = board.clear_lines();
+
+        assert_eq!(cleared.rows().collect::<Vec<_>>(), [19, 21]);
+        assert_eq!(
+            board.0,
+            crate::row_board!("...O......\nOOOOOOOOO.\n.OOOOOOOOO").0
+        );
+        assert_walls(&board);
+    }
+
+    #[test]
+    fn clear_tetris() {
+        let mut board = crate::row_board!(
+            "
+            OO........
+            OOOOOOOOOO
+            OOOOOOOOOO
+            OOOOOOOOOO
+            OOOOOOOOOO
+            "
+        );
+        board.0[1] |= 0b0000100000000000;
+
+        let cleared = board.clear_lines();
+
+        assert_eq!(cleared.count(), 4);
+        assert_eq!(cleared.rows().collect::<Vec<_>>(), [19, 20, 21, 22]);
+        assert_eq!(board.0[5], BOUNDS | 0b0000100000000000);
+        assert_eq!(board.0[MAX_Y as usize], BOUNDS | 0b0001100000000000);
+        assert!(board.0[..5].iter().all(|&r| r == BOUNDS));
+        assert_walls(&board);
+    }
+
+    #[test]
+    fn place_then_undo() {
+        let mut board = crate::row_board!(
+            "
+            ...O......
+            O..OO.....
+            OOOOOOOOO.
+            OO.OOOOOO.
+            OOOOOOOOO.
+            OOOOOOOOO.
+            "
+        );
+        let mut most_cleared = 0;
+
+        for piece in Piece::PIECES {
+            let mut spawn = Some(piece.row_start_pos());
+
+            while let Some(rotated) = spawn {
+                let mut pos = rotated;
+                while let Some(left) = board.try_left(pos) {
+                    pos = left;
+                }
+
+                let mut column = Some(pos);
+                while let Some(pos) = column {
+                    let mut dropped = pos;
+                    while let Some(down) = board.try_down(dropped) {
+                        dropped = down;
+                    }
+
+                    let before = board.clone();
+                    let undo = board.place(dropped);
+                    most_cleared = most_cleared.max(undo.cleared.count());
+
+                    let mut locked = before.clone();
+                    TetrisBoard::lock(&mut locked, dropped);
+                    locked.clear_lines();
+                    assert_eq!(board.0, locked.0);
+                    assert_eq!(board.1, locked.1);
+
+                    board.undo(undo);
+                    assert_eq!(board.0, before.0, "{dropped:?}");
+                    assert_eq!(board.1, before.1, "{dropped:?}");
+
+                    column = board.try_right(pos);
+                }
+
+                let start = piece.row_start_pos();
+                spawn = board.try_rot_cw(rotated).filter(|pos| pos.rot != start.rot);
+            }
+        }
+
+        // The I piece straight down the right side clears all but the row with a hole.
+        assert_eq!(most_cleared, 3);
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let board = Board::new();
+
+        for piece in Piece::PIECES {
+            let mut pos = Board::spawn_pos(piece);
+            assert_eq!(PiecePos::from(pos), RowBoard::spawn_pos(piece));
+
+            for _ in 0..3 {
+                let row_pos = PiecePos::from(pos);
+                assert_eq!(RowBoard::placement(row_pos), Board::placement(pos));
+                assert_eq!(BoardPos::from(row_pos), pos);
+
+                pos = TetrisBoard::try_left(&board, pos).unwrap();
+                pos = TetrisBoard::try_down(&board, pos).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use std::{fmt::Debug, hash::Hash};
+
+use crate::pieces::{Piece, Rotation};
+
+/// Width of the playfield in columns, shared by every board representation.
+pub const FIELD_WIDTH: usize = 10;
+/// Height of the visible playfield in rows. Rows above this are hidden spawn rows.
+pub const FIELD_HEIGHT: usize = 20;
+/// Rows a diagram can have, the visible ones plus the two hidden rows every board has.
+pub const DIAGRAM_HEIGHT: usize = FIELD_HEIGHT + 2;
+/// Rows any board can hold, counting the hidden ones.
+pub const MAX_HEIGHT: usize = FIELD_HEIGHT + 3;
+/// Upper bound for `TetrisBoard::state_index` of every board.
+pub const MAX_STATES: usize = 512;
+
+/// A single block as `(column, row)`, with columns counted from the left wall and rows from the
+/// floor, so `(0, 0)` is the bottom left cell. Rows from `FIELD_HEIGHT` up are hidden.
+pub type Cell = (u8, u8);
+
+/// Where a piece sits, independent of how a board stores it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub piece: Piece,
+    pub rot: Rotation,
+    /// Sorted, so placements covering the same cells compare equal.
+    pub cells: [Cell; 4],
+}
+
+impl Placement {
+    pub fn new(piece: Piece, rot: Rotation, mut cells: [Cell; 4]) -> Self {
+        cells.sort_unstable();
+
+        Self { piece, rot, cells }
+    }
+
+    /// Row of the lowest block.
+    pub fn lowest_row(&self) -> u8 {
+        self.cells.iter().map(|&(_, y)| y).min().unwrap_or_default()
+    }
+}
+
+/// Operations shared by `Board` and `RowBoard`, so game logic and searches can be written once
+/// and run on either. Pieces are moved by value: every `try_*` returns the moved position if it
+/// doesn't collide with anything, without changing the board.
+pub trait TetrisBoard: Clone + Default {
+    /// Position of a piece on this board, including its rotation.
+    type Pos: Copy + Eq + Hash + Debug;
+
+    fn spawn_pos(piece: Piece) -> Self::Pos;
+
+    fn piece(pos: Self::Pos) -> Piece;
+
+    fn rotation(pos: Self::Pos) -> Rotation;
+
+    fn placement(pos: Self::Pos) -> Placement;
+
+    /// Inverse of `placement`.
+    ///
+    /// # Panics
+    ///
+    /// If the placement doesn't fit in the rows this board can represent.
+    fn from_placement(placement: Placement) -> Self::Pos;
+
+    /// Index below `MAX_STATES` that is unique for every position of a piece in a single rotation,
+    /// used to mark positions as visited during a search.
+    fn state_index(pos: Self::Pos) -> usize;
+
+    fn collides(&self, pos: Self::Pos) -> bool;
+
+    fn try_up(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    fn try_down(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    fn try_left(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    fn try_right(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    fn try_rot_cw(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    fn try_rot_ccw(&self, pos: Self::Pos) -> Option<Self::Pos>;
+
+    /// Adds the blocks of the piece to the board.
+    fn lock(&mut self, pos: Self::Pos);
+
+    /// Removes the blocks of the piece from the board, undoing `lock` if no lines were cleared.
+    fn unlock(&mut self, pos: Self::Pos);
+
+    /// Removes all full visible rows, moving everything above them down. Returns the amount of
+    /// rows cleared.
+    fn clear_lines(&mut self) -> u8;
+
+    /// Whether the cell at column `x` and row `y`, counted from the floor, holds a block.
+    fn filled(&self, x: usize, y: usize) -> bool;
+
+    /// Puts a block of `piece` at column `x` and row `y`, counted from the floor. Does nothing when
+    /// the cell is already filled.
+    fn fill(&mut self, x: usize, y: usize, piece: Piece);
+
+    /// Features of the board, kept up to date by `lock`, `unlock`, `clear_lines` and `fill`.
+    fn features(&self) -> &BoardFeatures;
+
+    /// Computes the features again from the cells, after they were written to directly instead of
+    /// through the methods above.
+    fn refresh_features(&mut self);
+
+    /// Height of every column: one more than the row of its highest block, or 0 when empty. Scans
+    /// the whole board, `features` has the same heights without doing so.
+    fn column_heights(&self) -> [u8; FIELD_WIDTH] {
+        let mut res = [0; FIELD_WIDTH];
+
+        for (x, h) in res.iter_mut().enumerate() {
+            for y in (0..MAX_HEIGHT).rev() {
+                if self.filled(x, y) {
+                    *h = y as u8 + 1;
+                    break;
+                }
+            }
+        }
+
+        res
+    }
+}
+
+/// Statistics of a board that are cheap to keep up to date while placing pieces, so evaluating a
+/// board doesn't have to scan it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardFeatures {
+    /// Height of every column, like `TetrisBoard::column_heights`.
+    pub heights: [u8; FIELD_WIDTH],
+    /// Empty cells below the highest block of every column.
+    pub holes: [u8; FIELD_WIDTH],
+    /// Blocks in every row, counted from the floor.
+    pub row_fill: [u8; MAX_HEIGHT],
+}
+
+impl Default for BoardFeatures {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl BoardFeatures {
+    pub const EMPTY: Self = Self {
+        heights: [0; FIELD_WIDTH],
+        holes: [0; FIELD_WIDTH],
+        row_fill: [0; MAX_HEIGHT],
+    };
+
+    /// Computes the features of `board` from scratch.
+    pub fn new<B: TetrisBoard>(board: &B) -> Self {
+        let mut res = Self::EMPTY;
+
+        for x in 0..FIELD_WIDTH {
+            res.rescan_column(board, x);
+
+            for y in 0..res.heights[x] as usize {
+                if board.filled(x, y) {
+                    res.row_fill[y] += 1;
+                }
+            }
+        }
+
+        res
+    }
+
+    pub fn total_holes(&self) -> u32 {
+        self.holes.iter().map(|&h| h as u32).sum()
+    }
+
+    /// Accounts for a block added at `cell`, which must have been empty before.
+    pub fn add(&mut self, (x, y): Cell) {
+        let (x, y) = (x as usize, y as usize);
+        let height = self.heights[x] as usize;
+
+        self.row_fill[y] += 1;
+
+        if y < height {
+            self.holes[x] -= 1;
+        } else {
+            self.holes[x] += (y - height) as u8;
+            self.heights[x] = y as u8 + 1;
+        }
+    }
+
+    /// Accounts for the block at `cell` being removed from `board`, which no longer has it.
+    pub fn remove<B: TetrisBoard>(&mut self, board: &B, (x, y): Cell) {
+        let (x, y) = (x as usize, y as usize);
+
+        self.row_fill[y] -= 1;
+
+        let height = self.heights[x] as usize;
+
+        // Blocks above the height were already removed from `board` when the column was scanned
+        // for an earlier block of the same piece.
+        if y + 1 == height {
+            self.rescan_column(board, x);
+        } else if y + 1 < height {
+            self.holes[x] += 1;
+        }
+    }
+
+    /// Accounts for full rows being cleared from `board`, given as a bit per row counted from the
+    /// floor. Columns with blocks above the cleared rows just get lower, the others are scanned
+    /// again on `board`, which must already have the rows removed.
+    pub fn clear<B: TetrisBoard>(&mut self, board: &B, rows: u32) {
+        if rows == 0 {
+            return;
+        }
+
+        let count = rows.count_ones() as u8;
+        let highest = 31 - rows.leading_zeros() as u8;
+
+        let mut write = 0;
+        for read in 0..MAX_HEIGHT {
+            if rows & (1 << read) == 0 {
+                self.row_fill[write] = self.row_fill[read];
+                write += 1;
+            }
+        }
+        self.row_fill[write..].fill(0);
+
+        for x in 0..FIELD_WIDTH {
+            if self.heights[x] > highest + 1 {
+                self.heights[x] -= count;
+            } else {
+                self.rescan_column(board, x);
+            }
+        }
+    }
+
+    fn rescan_column<B: TetrisBoard>(&mut self, board: &B, x: usize) {
+        let height = (0..MAX_HEIGHT)
+            .rev()
+            .find(|&y| board.filled(x, y))
+            .map_or(0, |y| y + 1);
+
+        self.heights[x] = height as u8;
+        self.holes[x] = (0..height).filter(|&y| !board.filled(x, y)).count() as u8;
+    }
+}
+
+/// Error from reading a board diagram with `parse_diagram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseBoardError {
+    /// The diagram has more than `DIAGRAM_HEIGHT` rows.
+    TooManyRows(usize),
+    /// A row, counted from the floor, isn't `FIELD_WIDTH` cells wide.
+    RowWidth { row: usize, width: usize },
+    /// A cell is neither `.` nor a piece letter.
+    UnknownCell(char),
+    /// The lowercase cells don't form a single piece.
+    ActivePiece,
+}
+
+impl std::fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyRows(rows) => {
+                write!(
+                    f,
+                    "diagram has {rows} rows, at most {DIAGRAM_HEIGHT} fit on a board"
+                )
+            }
+            Self::RowWidth { row, width } => {
+                write!(
+                    f,
+                    "row {row} is {width} cells wide instead of {FIELD_WIDTH}"
+                )
+            }
+            Self::UnknownCell(c) => write!(f, "unknown cell '{c}'"),
+            Self::ActivePiece => write!(f, "lowercase cells don't form a single piece"),
+        }
+    }
+}
+
+impl std::error::Error for ParseBoardError {}
+
+/// Reads a diagram in the format boards are displayed in: a line per row, with `.` for empty
+/// cells and piece letters for blocks. Spaces and blank lines are ignored. The last row is the
+/// floor, so a diagram only needs to show the top of the stack.
+///
+/// Lowercase letters mark the active piece, like `Game` displays it. It is returned separately
+/// instead of being put on the board.
+pub fn parse_diagram<B: TetrisBoard>(s: &str) -> Result<(B, Option<B::Pos>), ParseBoardError> {
+    let rows: Vec<Vec<char>> = s
+        .lines()
+        .map(|line| {
+            line.chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect();
+
+    if rows.len() > DIAGRAM_HEIGHT {
+        return Err(ParseBoardError::TooManyRows(rows.len()));
+    }
+
+    let mut board = B::default();
+    let mut active = None;
+    let mut active_cells = Vec::with_capacity(4);
+
+    for (y, row) in rows.iter().rev().enumerate() {
+        if row.len() != FIELD_WIDTH {
+            return Err(ParseBoardError::RowWidth {
+                row: y,
+                width: row.len(),
+            });
+        }
+
+        for (x, &c) in row.iter().enumerate() {
+            if c == '.' {
+                continue;
+            }
+
+            let piece = Piece::from_letter(c.to_ascii_uppercase())
+                .ok_or(ParseBoardError::UnknownCell(c))?;
+
+            if c.is_ascii_lowercase() {
+                if *active.get_or_insert(piece) != piece {
+                    return Err(ParseBoardError::ActivePiece);
+                }
+                active_cells.push((x as u8, y as u8));
+            } else {
+                board.fill(x, y, piece);
+            }
+        }
+    }
+
+    let pos = match active {
+        Some(piece) => {
+            let pos = active_pos::<B>(piece, &active_cells).ok_or(ParseBoardError::ActivePiece)?;
+            Some(pos)
+        }
+        None => None,
+    };
+
+    Ok((board, pos))
+}
+
+/// Finds the rotation of `piece` covering `cells` by comparing their shape to the piece in every
+/// rotation it can reach from spawn.
+fn active_pos<B: TetrisBoard>(piece: Piece, cells: &[Cell]) -> Option<B::Pos> {
+    fn shape(cells: [Cell; 4]) -> [Cell; 4] {
+        let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or_default();
+        let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or_default();
+        let mut res = cells.map(|(x, y)| (x - min_x, y - min_y));
+        res.sort_unstable();
+        res
+    }
+
+    let cells: [Cell; 4] = cells.try_into().ok()?;
+    let board = B::default();
+    let mut pos = B::spawn_pos(piece);
+
+    for _ in 0..4 {
+        if shape(B::placement(pos).cells) == shape(cells) {
+            return Some(B::from_placement(Placement::new(
+                piece,
+                B::rotation(pos),
+                cells,
+            )));
+        }
+
+        pos = board.try_rot_cw(pos)?;
+    }
+
+    None
+}
+
+/// Reads a `Board` from a diagram, panicking if it is invalid. See `parse_diagram`.
+#[macro_export]
+macro_rules! board {
+    ($diagram:expr) => {
+        $diagram
+            .parse::<$crate::board::Board>()
+            .expect("invalid board diagram")
+    };
+}
+
+/// Reads a `RowBoard` from a diagram, panicking if it is invalid. See `parse_diagram`.
+#[macro_export]
+macro_rules! row_board {
+    ($diagram:expr) => {
+        $diagram
+            .parse::<$crate::row_board::RowBoard>()
+            .expect("invalid board diagram")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::*, rng::SequenceRng, row_board::*, Game, RowGame};
+
+    use super::*;
+
+    #[test]
+    fn board_display_round_trip() {
+        let mut board = Board::new();
+        board.lock([200, 201, 202, 203], Piece::I);
+        board.lock([204, 205, 214, 215], Piece::O);
+        board.lock([186, 196, 197, 198], Piece::J);
+
+        let parsed: Board = board.to_string().parse().unwrap();
+
+        assert_eq!(parsed.0, board.0);
+    }
+
+    #[test]
+    fn diagram_is_bottom_aligned() {
+        let board = board!(
+            "
+            . . . . . . . . . .
+            . . . . . . . T . .
+            I I I I . . T T T .
+            "
+        );
+        let row_board = row_board!(
+            "
+            ..........
+            .......O..
+            OOOO..OOO.
+            "
+        );
+
+        assert_eq!(board.0[207], Some(Piece::T));
+        assert_eq!(board.0[210], Some(Piece::I));
+        assert_eq!(board.column_heights(), [1, 1, 1, 1, 0, 0, 1, 2, 1, 0]);
+        assert_eq!(RowBoard::from(&board).0, row_board.0);
+    }
+
+    #[test]
+    fn active_piece() {
+        let rng = SequenceRng::new(vec![Piece::I]);
+        let game = Game::<_, Board>::from_diagram(
+            "
+            . . . . . . . . . .
+            . . . . . . . . . .
+            . . . . . . t . . .
+            . . . . . t t . . .
+            . . . . . . t . . .
+            I I I I . . . . . .
+            ",
+            rng.clone(),
+            18,
+        )
+        .unwrap();
+        let row_game = RowGame::from_diagram(&game.to_string(), rng, 18).unwrap();
+
+        assert_eq!(game.current(), Piece::T);
+        assert_eq!(game.rot(), Rotation::Down);
+        assert_eq!(game.board.column_heights(), [1, 1, 1, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            RowBoard::placement(row_game.pos),
+            Board::placement(game.pos)
+        );
+        assert_eq!(
+            row_game.to_string().trim(),
+            game.to_string().replace('I', "O").trim()
+        );
+    }
+
+    #[test]
+    fn features_follow_locks_and_clears() {
+        let mut board = board!(
+            "
+            . . . . . . . . . .
+            . . O O . . . . . .
+            I I . . I I I I . .
+            "
+        );
+        assert_eq!(board.1, BoardFeatures::new(&board));
+        assert_eq!(board.1.heights, [1, 1, 2, 2, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(board.1.holes, [0, 0, 1, 1, 0, 0, 0, 0, 0, 0]);
+
+        board.lock([208, 209, 218, 219], Piece::O);
+        board.lock([167, 177, 187, 197], Piece::I);
+        assert_eq!(board.1, BoardFeatures::new(&board));
+        assert_eq!(board.1.holes[7], 1);
+
+        board.unlock([167, 177, 187, 197]);
+        board.lock([204, 205, 206, 207], Piece::I);
+        board.lock([190, 191, 200, 201], Piece::O);
+        assert_eq!(board.1, BoardFeatures::new(&board));
+        assert_eq!(board.1.row_fill[..3], [8, 10, 2]);
+
+        assert_eq!(board.clear_lines(), 1);
+        assert_eq!(board.1, BoardFeatures::new(&board));
+        assert_eq!(board.1.heights, [2, 2, 0, 0, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(board.1.total_holes(), 0);
+    }
+
+    #[test]
+    fn fill_twice() {
+        fn check<B: TetrisBoard + Default>() {
+            let mut board = B::default();
+            board.fill(3, 0, Piece::T);
+            board.fill(3, 2, Piece::T);
+            let features = *board.features();
+
+            board.fill(3, 0, Piece::I);
+            board.fill(3, 2, Piece::I);
+
+            assert_eq!(*board.features(), features);
+            assert_eq!(features.holes[3], 1);
+        }
+
+        check::<Board>();
+        check::<RowBoard>();
+    }
+
+    #[test]
+    fn invalid_diagrams() {
+        let err = |s: &str| s.parse::<Board>().unwrap_err();
+
+        assert_eq!(
+            err(&". . . . . . . . . .\n".repeat(23)),
+            ParseBoardError::TooManyRows(23)
+        );
+        assert_eq!(
+            err(". . .\n. . . . . . . . . ."),
+            ParseBoardError::RowWidth { row: 1, width: 3 }
+        );
+        assert_eq!(
+            err(". . . . . x . . . ."),
+            ParseBoardError::UnknownCell('x')
+        );
+        assert_eq!(err(". . . . . t t t . ."), ParseBoardError::ActivePiece);
+        assert_eq!(
+            err(". . . . . t t t . .\n. . . . . . s . . ."),
+            ParseBoardError::ActivePiece
+        );
+    }
+}
@@ -1,13 +1,7 @@
 use std::collections::HashSet;
 
-use ai::TetrisAi;
-use game::{
-    board::*,
-    pieces::Piece,
-    rng::*,
-    row_board::{PiecePos as PP, RowBoard},
-    Game,
-};
+use ai::{eval::EvalWeights, TetrisAi};
+use game::{rng::*, tetris_board::TetrisBoard, GameState};
 
 fn main() {
     // piece_rot();
@@ -16,24 +10,52 @@ fn main() {
 }
 
 pub fn play() {
-    let mut ai = TetrisAi::<SevenBag>::new(19);
+    let seed = random_seed();
+    println!("seed: {seed}");
+
+    let mut ai = TetrisAi::<SevenBag>::from_seed(seed, 19);
+
+    if let Some(path) = std::env::args().nth(1) {
+        match EvalWeights::from_file(&path) {
+            Ok(weights) => ai.weights = weights,
+            Err(err) => {
+                println!("{path}: {err}");
+                return;
+            }
+        }
+    }
 
     let stdin = std::io::stdin();
 
     loop {
+        if let GameState::Finished(outcome) = ai.game.state {
+            println!("game over! {outcome}");
+            break;
+        }
+
+        let before = ai.clone();
+
         match time_this::time!(ai.find_best_move()) {
             Some((pos, score)) => {
-                ai.pos = pos;
+                ai.game.pos = pos;
 
-                ai.lock();
+                ai.game.lock();
 
-                dbg!(ai.highest_blocks);
+                println!("chosen board:\n{}\nscore: {}", ai.game.board, score);
+                println!("enter ? to see the best moves it was picked from");
 
-                println!("chosen board:\n{}\nscore: {}", ai.board, score);
+                let mut line = String::new();
+                let _ = stdin.read_line(&mut line);
 
-                let _ = stdin.read_line(&mut String::new());
+                if line.trim() == "?" {
+                    before.print_candidates(5);
+                    let _ = stdin.read_line(&mut String::new());
+                }
+            }
+            None => {
+                println!("no possible moves found! board:\n{}", ai.game.board);
+                break;
             }
-            None => panic!("no possible moves found! board:\n{}", ai.board),
         }
     }
 }
@@ -218,7 +240,7 @@ pub fn piece_rot() {
 
     println!("{g}");
 
-    let mut ai = ai::row_ai::RowTetrisAi::from_game(g, 3);
+    let mut ai = ai::row_ai::RowTetrisAi::from_game(g);
 
     let score = ai.eval();
 
@@ -232,22 +254,13 @@ pub fn piece_rot() {
 
         println!("{}", ai.game);
 
-        let highest_blocks_old = ai.highest_blocks;
-
-        let masks = ai.game.pos.get_masks();
-        masks.into_iter().enumerate().for_each(|(i, m)|
-            ai.game.board.0[ai.game.pos.y as usize + i] |= m
-        );
+        ai.game.board.lock(*u);
 
         let score = time_this::time!(ai.eval());
 
         println!("eval score: {}", score);
 
-        masks.into_iter().enumerate().for_each(|(i, m)|
-            ai.game.board.0[ai.game.pos.y as usize + i] ^= m
-        );
-
-        ai.highest_blocks = highest_blocks_old;
+        ai.game.board.unlock(*u);
     }
 
     println!(